@@ -1,3 +1,3 @@
-from .otters import Pipeline
+from .otters import Pipeline, PipelineError, SchemaError, postgres, read_lineage
 from .schema import Schema
 from .batcher import Batcher
\ No newline at end of file
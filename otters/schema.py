@@ -4,11 +4,17 @@ class Schema:
     Str: str = "utf8"
     F64: str = "float64"
     I64: str = "int64"
+    Ts: str = "timestamp"
+    Bool: str = "bool"
+    Date: str = "date"
 
     _TYPE_MAP: dict[str, pa.DataType] = {
         "utf8":    pa.utf8(),
         "float64": pa.float64(),
         "int64":   pa.int64(),
+        "timestamp": pa.timestamp("us"),
+        "bool":    pa.bool_(),
+        "date":    pa.date32(),
     }
 
     fields: dict[str, str]
@@ -1,44 +1,248 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use pyo3::prelude::*;
-use pyo3::types::PyList;
-use crossbeam_channel::{Receiver, Sender};
+use pyo3::exceptions::PyTypeError;
+use pyo3::types::{PyDict, PyList, PyString, PyTuple};
+use crossbeam_channel::{Receiver, RecvTimeoutError};
+use arrow::array::{ArrayRef, BooleanBuilder, Float64Builder, Int32Builder, Int64Builder, StringBuilder};
+use arrow::compute::{CastOptions, cast_with_options};
+use arrow::datatypes::{DataType, SchemaRef, TimeUnit};
+use arrow::error::ArrowError;
 use arrow::record_batch::RecordBatch;
 use arrow::pyarrow::FromPyArrow;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use crate::channel::Output;
+use crate::error::{StageError, StageResult};
 
+/// turns python rows straight into arrow columns for a known schema, no pyarrow involved
+///
+/// rows can be dicts (looked up by column name, extra keys are ignored), tuples / lists
+/// (by position, namedtuples too) or dataclasses (by attribute)
 pub struct Batcher {
-    batch_size: usize,
-    schema_fields: Vec<String>,
+    schema: SchemaRef,
+    /// rows converted so far, so errors can say which row was bad
+    rows: usize,
+}
+
+/// the column types the batcher can build, anything else needs the pyarrow path (no schema=)
+pub fn check_schema(schema: &SchemaRef) -> Result<(), StageError> {
+    for field in schema.fields() {
+        if Column::new(field.data_type(), 0).is_none() {
+            return Err(StageError::WrongType {
+                column: field.name().clone(),
+                expected: "bool, int, uint, float, string, timestamp or date for a python source",
+                found: field.data_type().clone(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// one builder per column, ints/floats/strings are built wide and cast to the schema's type after
+enum Column {
+    Bool(BooleanBuilder),
+    Int(Int64Builder),
+    Float(Float64Builder),
+    Str(StringBuilder),
+    Timestamp(TimeUnit, Int64Builder),
+    Date(Int32Builder),
+}
+
+impl Column {
+    fn new(data_type: &DataType, capacity: usize) -> Option<Self> {
+        Some(match data_type {
+            DataType::Boolean => Column::Bool(BooleanBuilder::with_capacity(capacity)),
+            DataType::Int8 | DataType::Int16 | DataType::Int32 | DataType::Int64
+            | DataType::UInt8 | DataType::UInt16 | DataType::UInt32 | DataType::UInt64 => {
+                Column::Int(Int64Builder::with_capacity(capacity))
+            }
+            DataType::Float32 | DataType::Float64 => Column::Float(Float64Builder::with_capacity(capacity)),
+            DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View => {
+                Column::Str(StringBuilder::with_capacity(capacity, capacity * 8))
+            }
+            DataType::Timestamp(unit, _) => Column::Timestamp(*unit, Int64Builder::with_capacity(capacity)),
+            DataType::Date32 => Column::Date(Int32Builder::with_capacity(capacity)),
+            _ => return None,
+        })
+    }
+
+    fn append_null(&mut self) {
+        match self {
+            Column::Bool(b) => b.append_null(),
+            Column::Int(b) | Column::Timestamp(_, b) => b.append_null(),
+            Column::Float(b) => b.append_null(),
+            Column::Str(b) => b.append_null(),
+            Column::Date(b) => b.append_null(),
+        }
+    }
+
+    /// Err is the python type name, the caller knows the row and column
+    fn append(&mut self, value: &Bound<'_, PyAny>) -> Result<(), String> {
+        let wrong = || value.get_type().name().map(|n| n.to_string()).unwrap_or_default();
+        match self {
+            Column::Bool(b) => b.append_value(value.extract().map_err(|_| wrong())?),
+            Column::Int(b) => b.append_value(value.extract().map_err(|_| wrong())?),
+            Column::Float(b) => b.append_value(value.extract().map_err(|_| wrong())?),
+            Column::Str(b) => b.append_value(value.cast::<PyString>().map_err(|_| wrong())?.to_cow().map_err(|_| wrong())?),
+            Column::Timestamp(unit, b) => {
+                // ints are taken as already being in the column's unit, like pyarrow does
+                if let Ok(raw) = value.extract::<i64>() {
+                    b.append_value(raw);
+                    return Ok(());
+                }
+                let utc = datetime_utc(value).ok_or_else(wrong)?;
+                let ts = match unit {
+                    TimeUnit::Second => Some(utc.timestamp()),
+                    TimeUnit::Millisecond => Some(utc.timestamp_millis()),
+                    TimeUnit::Microsecond => Some(utc.timestamp_micros()),
+                    TimeUnit::Nanosecond => utc.timestamp_nanos_opt(),
+                };
+                b.append_value(ts.ok_or_else(|| format!("{} (out of range for nanoseconds)", utc))?);
+            }
+            Column::Date(b) => {
+                let date: NaiveDate = value.extract().map_err(|_| wrong())?;
+                b.append_value((date - NaiveDate::default()).num_days() as i32);
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> ArrayRef {
+        match self {
+            Column::Bool(b) => Arc::new(b.finish()),
+            Column::Int(b) | Column::Timestamp(_, b) => Arc::new(b.finish()),
+            Column::Float(b) => Arc::new(b.finish()),
+            Column::Str(b) => Arc::new(b.finish()),
+            Column::Date(b) => Arc::new(b.finish()),
+        }
+    }
+}
+
+/// naive datetimes are taken as utc, aware ones converted to it
+fn datetime_utc(value: &Bound<'_, PyAny>) -> Option<DateTime<Utc>> {
+    if let Ok(naive) = value.extract::<NaiveDateTime>() {
+        return Some(naive.and_utc());
+    }
+    let utc = value.py().import("datetime").and_then(|m| m.getattr("timezone")?.getattr("utc")).ok()?;
+    value.call_method1("astimezone", (utc,)).ok()?.extract().ok()
+}
+
+enum Row<'a, 'py> {
+    Dict(&'a Bound<'py, PyDict>),
+    Seq(Vec<Bound<'py, PyAny>>),
+    Attrs(&'a Bound<'py, PyAny>),
 }
 
 impl Batcher {
-    pub fn new(batch_size: usize, schema_fields: Vec<String>) -> Self {
-        Self { batch_size, schema_fields }
+    pub fn new(schema: SchemaRef) -> Self {
+        Self { schema, rows: 0 }
+    }
+
+    /// builds one batch from a buffer of rows, under the GIL once for the whole buffer
+    pub fn flush(&mut self, py: Python<'_>, rows: &[Py<PyAny>]) -> Result<RecordBatch, StageError> {
+        let fields = self.schema.fields();
+        let mut columns: Vec<Column> = fields.iter()
+            .map(|f| Column::new(f.data_type(), rows.len()).expect("checked by check_schema"))
+            .collect();
+        let keys: Vec<Bound<'_, PyString>> = fields.iter().map(|f| PyString::new(py, f.name())).collect();
+
+        for row in rows {
+            let row = row.bind(py);
+            let bad = |msg: String| StageError::Row { row: self.rows, message: msg };
+            let access = if let Ok(dict) = row.cast::<PyDict>() {
+                Row::Dict(dict)
+            } else if row.is_instance_of::<PyTuple>() || row.is_instance_of::<PyList>() {
+                let values: Vec<Bound<'_, PyAny>> = row.try_iter()?.collect::<PyResult<_>>()?;
+                if values.len() != fields.len() {
+                    return Err(bad(format!("has {} values, the schema has {} columns", values.len(), fields.len())));
+                }
+                Row::Seq(values)
+            } else if row.hasattr("__dataclass_fields__")? {
+                Row::Attrs(row)
+            } else {
+                return Err(StageError::Python(PyTypeError::new_err(format!(
+                    "row {} is {}, expected a dict, tuple or dataclass", self.rows, row.get_type().name()?
+                ))));
+            };
+
+            for (i, field) in fields.iter().enumerate() {
+                let value = match &access {
+                    Row::Dict(dict) => dict.get_item(&keys[i])?,
+                    Row::Seq(values) => Some(values[i].clone()),
+                    Row::Attrs(obj) => obj.getattr_opt(&keys[i])?,
+                };
+                let Some(value) = value else {
+                    return Err(bad(format!("has no '{}'", field.name())));
+                };
+                if value.is_none() {
+                    if !field.is_nullable() {
+                        return Err(bad(format!("'{}' is None but the column isn't nullable", field.name())));
+                    }
+                    columns[i].append_null();
+                } else if let Err(found) = columns[i].append(&value) {
+                    return Err(bad(format!("'{}' is {}, expected {}", field.name(), found, field.data_type())));
+                }
+            }
+            self.rows += 1;
+        }
+
+        // int64 -> int8 etc. errors on overflow instead of turning the value into a null
+        let options = CastOptions { safe: false, ..Default::default() };
+        let arrays = fields.iter().zip(columns.iter_mut())
+            .map(|(field, column)| {
+                cast_with_options(&column.finish(), field.data_type(), &options)
+                    .map_err(|e| ArrowError::CastError(format!("column '{}': {}", field.name(), e)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(RecordBatch::try_new(self.schema.clone(), arrays)?)
     }
 }
 
 /// spawn batcher thread
-/// 
+///
 /// receives pytho dicts from source thread,
 /// buffers them, and then flushes as Arrow RecordBatches
-/// 
-/// channel still carries PY<PyAny> on both sides
+///
+/// channel still carries PY<PyAny> on both sides. with a schema the rows go
+/// through Batcher, without one pyarrow infers the types from each buffer
+///
+/// with a flush interval a partial batch goes out once its first row has waited that long,
+/// recv_timeout sleeps until then so a quiet feed doesn't spin
 pub fn spawn_batcher(
     receiver: Receiver<Py<PyAny>>,
-    sender: Sender<RecordBatch>,
+    sender: Output,
     batch_size: usize,
-) -> std::thread::JoinHandle<()> {
+    flush_interval: Option<Duration>,
+    schema: Option<SchemaRef>,
+) -> std::thread::JoinHandle<StageResult> {
 
     // straight forward buffer batching stuff
     std::thread::spawn(move || {
         let mut buffer: Vec<Py<PyAny>> = Vec::with_capacity(batch_size);
+        // when the first row in the buffer arrived
+        let mut oldest: Option<Instant> = None;
+        let mut batches = 0usize;
+        let mut batcher = schema.map(Batcher::new);
+        let mut flush = |rows: &[Py<PyAny>]| Python::attach(|py| match batcher.as_mut() {
+            Some(batcher) => batcher.flush(py, rows),
+            None => flush_pyarrow(py, rows),
+        });
 
         loop {
-            match receiver.recv() {
-                Ok(item) => buffer.push(item),
-                Err(_) => {
+            let received = match (oldest, flush_interval) {
+                (Some(oldest), Some(interval)) => receiver.recv_timeout(interval.saturating_sub(oldest.elapsed())),
+                _ => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            match received {
+                Ok(item) => {
+                    oldest.get_or_insert_with(Instant::now);
+                    buffer.push(item);
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
                     if !buffer.is_empty() {
-                        if let Some(batch) = flush(&buffer) {
-                            sender.send(batch).ok();
-                        }
+                        let batch = flush(&buffer).map_err(|e| e.at(batches))?;
+                        sender.send(batch);
                     }
                     break;
                 }
@@ -51,24 +255,28 @@ pub fn spawn_batcher(
                 }
             }
 
-            if buffer.len() >= batch_size {
-                if let Some(batch) = flush(&buffer) {
-                    sender.send(batch).ok();
-                    buffer.clear();
+            let overdue = oldest.zip(flush_interval).is_some_and(|(oldest, interval)| oldest.elapsed() >= interval);
+            if buffer.len() >= batch_size || overdue {
+                let batch = flush(&buffer).map_err(|e| e.at(batches))?;
+                buffer.clear();
+                oldest = None;
+                batches += 1;
+                // downstream hung up, dropping our receiver stops the source too
+                if !sender.send(batch) {
+                    break;
                 }
             }
         }
+        Ok(())
     })
 }
 
 ///converts buffer of py dicts to single arrow recordbatch
 /// aquires gil once per buffer/batch
-fn flush(rows: &[Py<PyAny>]) -> Option<RecordBatch> {
-    Python::attach(|py| {
-        let lst = PyList::new(py, rows.iter().map(|r| r.bind(py))).ok()?;
-        let pa = py.import("pyarrow").ok()?;
-        let rb_class = pa.getattr("RecordBatch").ok()?;
-        let py_batch = rb_class.call_method1("from_pylist", (lst,)).ok()?;
-        RecordBatch::from_pyarrow_bound(&py_batch).ok()
-    })
+fn flush_pyarrow(py: Python<'_>, rows: &[Py<PyAny>]) -> Result<RecordBatch, StageError> {
+    let lst = PyList::new(py, rows.iter().map(|r| r.bind(py)))?;
+    let pa = py.import("pyarrow")?;
+    let rb_class = pa.getattr("RecordBatch")?;
+    let py_batch = rb_class.call_method1("from_pylist", (lst,))?;
+    Ok(RecordBatch::from_pyarrow_bound(&py_batch)?)
 }
\ No newline at end of file
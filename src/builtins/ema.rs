@@ -1,34 +1,75 @@
-use arrow::array::Float64Array;
+use arrow::datatypes::{DataType, Field};
 use arrow::record_batch::RecordBatch;
-use crate::compute::ComputeStage;
-use crate::builtins::zscore::append_column;
+use crate::compute::{ColumnKind, ComputeStage};
+use crate::error::StageError;
+use crate::builtins::keyed::Keyed;
+use crate::builtins::nulls::{NullPolicy, NullState};
+use crate::builtins::zscore::{append_column, f64_column, output_type_name};
+
+/// ema state for one partition
+#[derive(Default)]
+struct EmaState {
+    current: Option<f64>,
+    nulls: NullState<f64>,
+}
 
 pub struct Ema {
     column: String,
 
     // this is the lookback period
     span: usize,
-    current: Option<f64>,
+    nulls: NullPolicy,
+    output_type: DataType,
+    // last ema value, one per partition
+    current: Keyed<EmaState>,
 }
 
 impl Ema {
-    pub fn new(column: String, span: usize) -> Self {
+    pub fn new(
+        column: String,
+        span: usize,
+        partition_by: Option<String>,
+        nulls: NullPolicy,
+        output_type: DataType,
+    ) -> Self {
         Self { 
-            column, span, current: None }
+            column, span, nulls, output_type, current: Keyed::new(partition_by) }
+    }
+
+    fn output_name(&self) -> String {
+        format!("{}_ema_{}", self.column, self.span)
     }
 }
 
 impl ComputeStage for Ema {
-    fn process(&mut self, batch: RecordBatch) -> RecordBatch {
-        let col_idx = batch.schema().index_of(&self.column)
-            .expect("column not found");
+    fn name(&self) -> &'static str {
+        "ema"
+    }
 
-        // we need to downcast from the generic arrow array to float 64 array
-        // this is so we can call .values() to get the raw &[f64] slice
-        let col = batch.column(col_idx)
-            .as_any()
-            .downcast_ref::<Float64Array>()
-            .expect("column is not f64");
+    fn input_columns(&self) -> Vec<(String, ColumnKind)> {
+        let mut cols = vec![(self.column.clone(), ColumnKind::Numeric)];
+        cols.extend(self.current.input_column());
+        cols
+    }
+
+    fn output_fields(&self) -> Vec<Field> {
+        vec![Field::new(self.output_name(), self.output_type.clone(), true)]
+    }
+
+    fn params(&self) -> serde_json::Value {
+        serde_json::json!({
+            "column": self.column,
+            "span": self.span,
+            "partition_by": self.current.column(),
+            "null_policy": self.nulls.name(),
+            "output_dtype": output_type_name(&self.output_type),
+        })
+    }
+
+    fn process(&mut self, batch: RecordBatch) -> Result<RecordBatch, StageError> {
+        // f64_column casts any numeric column to float64, so we can read the raw f64 buffer directly
+        let col = f64_column(&batch, &self.column)?;
+        let slots = self.current.slots(&batch)?;
 
         // alpha for smoothing
         // std func is 2 / (span + 1)
@@ -37,19 +78,25 @@ impl ComputeStage for Ema {
         let alpha = 2.0 / (self.span as f64 + 1.0);
         let mut output = Vec::with_capacity(col.len());
 
-        // col.values() returns a raw &[f64] with no boing or heap alloc
-        // just a slice directly into the arrow buf
-        // this is why arrow compute is so fast cause theres no copy
-        for val in col.values() {
-            let ema = match self.current {
-                None => *val,
+        // col.iter() reads straight out of the arrow buf like values() does,
+        // but also checks the validity bitmap so nulls come back as None
+        for (val, slot) in col.iter().zip(slots) {
+            let state = self.current.get_mut(slot);
+            // no window, so propagate only nulls the row itself
+            let row = self.nulls.row(val, f64::NAN, &mut state.nulls, None);
+            let Some(val) = row.push else {
+                output.push(None);
+                continue;
+            };
+            let ema = match state.current {
+                None => val,
                 // new = alpha * current + (1 - alpha) * previous
                 Some(prev) => alpha * val + (1.0 - alpha) * prev,
             };
-            self.current = Some(ema);
-            output.push(ema);
+            state.current = Some(ema);
+            output.push(Some(ema));
         }
 
-        append_column(batch, output, format!("{}_ema_{}", self.column, self.span))
+        append_column(batch, output, self.output_name(), &self.output_type)
     }
 }
\ No newline at end of file
@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+use arrow::array::{Array, ArrayRef, AsArray};
+use arrow::record_batch::RecordBatch;
+use crate::compute::ColumnKind;
+use crate::error::StageError;
+
+/// rolling state for a builtin, either one shared state or one per partition key
+///
+/// with partition_by="symbol" every symbol gets its own window so rows from
+/// different tickers never mix, output rows stay in input order.
+/// states live in a vec and keys map to a slot in it, so the per row
+/// work is one hash lookup (or none for dictionary columns, see slots())
+pub struct Keyed<S> {
+    column: Option<String>,
+    slots: HashMap<String, usize>,
+    null_slot: Option<usize>,
+    states: Vec<S>,
+}
+
+impl<S: Default> Keyed<S> {
+    pub fn new(column: Option<String>) -> Self {
+        Self {
+            column,
+            slots: HashMap::new(),
+            null_slot: None,
+            states: Vec::new(),
+        }
+    }
+
+    /// the partition_by column, None when there's one shared state
+    pub fn column(&self) -> Option<&str> {
+        self.column.as_deref()
+    }
+
+    /// the key column as a stage input, for ComputeStage::input_columns
+    pub fn input_column(&self) -> Option<(String, ColumnKind)> {
+        self.column.clone().map(|c| (c, ColumnKind::Key))
+    }
+
+    pub fn get_mut(&mut self, slot: usize) -> &mut S {
+        &mut self.states[slot]
+    }
+
+    /// state slot for every row of the batch
+    ///
+    /// dictionary columns only hash each distinct dictionary value once,
+    /// rows then go through the dictionary key. null keys share their own state
+    pub fn slots(&mut self, batch: &RecordBatch) -> Result<Vec<usize>, StageError> {
+        let Some(column) = self.column.clone() else {
+            if self.states.is_empty() {
+                self.states.push(S::default());
+            }
+            return Ok(vec![0; batch.num_rows()]);
+        };
+
+        let idx = batch.schema().index_of(&column)
+            .map_err(|_| StageError::ColumnNotFound(column.clone()))?;
+        let col = batch.column(idx);
+        let wrong_type = || StageError::WrongType {
+            column: column.clone(),
+            expected: ColumnKind::Key.describe(),
+            found: col.data_type().clone(),
+        };
+
+        if let Some(dict) = col.as_any_dictionary_opt() {
+            let values = strings(dict.values()).ok_or_else(wrong_type)?;
+            let value_slots: Vec<usize> = values.into_iter()
+                .map(|v| self.slot(v))
+                .collect();
+            let keys = dict.normalized_keys();
+            return Ok((0..col.len())
+                .map(|i| if col.is_null(i) { self.slot(None) } else { value_slots[keys[i]] })
+                .collect());
+        }
+
+        let values = strings(col).ok_or_else(wrong_type)?;
+        Ok(values.into_iter().map(|v| self.slot(v)).collect())
+    }
+
+    fn slot(&mut self, key: Option<&str>) -> usize {
+        let next = self.states.len();
+        let slot = match key {
+            Some(k) => match self.slots.get(k) {
+                Some(slot) => *slot,
+                None => {
+                    self.slots.insert(k.to_string(), next);
+                    next
+                }
+            },
+            None => *self.null_slot.get_or_insert(next),
+        };
+        if slot == next {
+            self.states.push(S::default());
+        }
+        slot
+    }
+}
+
+/// row values of any arrow string array, None if it isn't one
+fn strings(array: &ArrayRef) -> Option<Vec<Option<&str>>> {
+    if let Some(a) = array.as_string_opt::<i32>() {
+        return Some(a.iter().collect());
+    }
+    if let Some(a) = array.as_string_opt::<i64>() {
+        return Some(a.iter().collect());
+    }
+    array.as_string_view_opt().map(|a| a.iter().collect())
+}
\ No newline at end of file
@@ -1,4 +1,7 @@
+pub mod keyed;
+pub mod nulls;
 pub mod rolling_mean;
 pub mod zscore;
 pub mod ema;
-pub mod vwap;
\ No newline at end of file
+pub mod vwap;
+pub mod select;
\ No newline at end of file
@@ -0,0 +1,91 @@
+use pyo3::prelude::*;
+use pyo3::exceptions::PyValueError;
+
+/// what a builtin does with null input rows
+///
+/// skip      - null rows leave the state alone and get a null output (default)
+/// propagate - nulls take up a slot in the window and every output whose window
+///             holds a null is null. stages without a window (ema) treat this like skip
+/// ffill     - nulls are replaced by the last valid value of the same partition,
+///             rows before the first valid value are skipped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NullPolicy {
+    Skip,
+    Propagate,
+    ForwardFill,
+}
+
+impl NullPolicy {
+    pub fn parse(s: &str) -> PyResult<Self> {
+        match s {
+            "skip" => Ok(NullPolicy::Skip),
+            "propagate" => Ok(NullPolicy::Propagate),
+            "ffill" => Ok(NullPolicy::ForwardFill),
+            other => Err(PyValueError::new_err(format!(
+                "unknown null_policy '{}', expected 'skip', 'propagate' or 'ffill'", other
+            ))),
+        }
+    }
+
+    /// the null_policy= string this was parsed from
+    pub fn name(&self) -> &'static str {
+        match self {
+            NullPolicy::Skip => "skip",
+            NullPolicy::Propagate => "propagate",
+            NullPolicy::ForwardFill => "ffill",
+        }
+    }
+
+    /// works out what one row feeds into the stage's state and whether its output is null
+    ///
+    /// `gap` is what gets pushed for a propagated null so it still takes up a window
+    /// slot, it should be NaN so the windows treat it like any other bad value
+    pub fn row<T: Copy>(
+        &self,
+        value: Option<T>,
+        gap: T,
+        state: &mut NullState<T>,
+        window: Option<usize>,
+    ) -> Row<T> {
+        state.since_null = match value {
+            Some(_) => state.since_null.map(|n| n.saturating_add(1)),
+            None => Some(0),
+        };
+
+        match (self, value) {
+            (NullPolicy::ForwardFill, Some(v)) => {
+                state.last = Some(v);
+                Row { push: Some(v), null_out: false }
+            }
+            (NullPolicy::ForwardFill, None) => Row { push: state.last, null_out: state.last.is_none() },
+            (NullPolicy::Propagate, v) if window.is_some() => {
+                let null_in_window = state.since_null.is_some_and(|n| n < window.unwrap());
+                Row { push: Some(v.unwrap_or(gap)), null_out: null_in_window }
+            }
+            (_, Some(v)) => Row { push: Some(v), null_out: false },
+            (_, None) => Row { push: None, null_out: true },
+        }
+    }
+}
+
+/// what NullPolicy::row decided for one row
+pub struct Row<T> {
+    /// value to feed into the state, None leaves it untouched
+    pub push: Option<T>,
+    /// output for this row is null no matter what the state says
+    pub null_out: bool,
+}
+
+/// per partition bookkeeping for the null policy
+pub struct NullState<T> {
+    // last valid value, for ffill
+    last: Option<T>,
+    // rows since the last null, None if there hasn't been one
+    since_null: Option<usize>,
+}
+
+impl<T> Default for NullState<T> {
+    fn default() -> Self {
+        Self { last: None, since_null: None }
+    }
+}
\ No newline at end of file
@@ -1,69 +1,126 @@
 use std::collections::VecDeque;
-use std::sync::Arc;
-use arrow::array::{ArrayRef, Float64Array};
-use arrow::datatypes::{DataType, Field, Schema};
+use arrow::datatypes::{DataType, Field};
 use arrow::record_batch::RecordBatch;
-use crate::compute::ComputeStage;
+use crate::compute::{ColumnKind, ComputeStage};
+use crate::error::StageError;
+use crate::builtins::keyed::Keyed;
+use crate::builtins::nulls::{NullPolicy, NullState};
+use crate::builtins::zscore::{append_column, f64_column, output_type_name};
+
+/// rolling window state for one partition
+#[derive(Default)]
+struct Window {
+    history: VecDeque<f64>,
+    sum: f64,
+    // NaN/inf in the window are kept out of sum, the mean falls back to
+    // summing the window while any are in there
+    non_finite: usize,
+    nulls: NullState<f64>,
+}
+
+impl Window {
+    fn push(&mut self, val: f64, window: usize) {
+        self.history.push_back(val);
+        if val.is_finite() {
+            self.sum += val;
+        } else {
+            self.non_finite += 1;
+        }
+
+        if self.history.len() > window {
+            let old = self.history.pop_front().unwrap();
+            if old.is_finite() {
+                self.sum -= old;
+            } else {
+                self.non_finite -= 1;
+            }
+        }
+    }
+
+    /// only valid once the window is full
+    fn mean(&self, window: usize) -> f64 {
+        if self.non_finite > 0 {
+            return self.history.iter().sum::<f64>() / window as f64;
+        }
+        self.sum / window as f64
+    }
+}
 
 pub struct RollingMean {
     column: String,
     window: usize,
-    history: VecDeque<f64>,
-    sum: f64,
+    nulls: NullPolicy,
+    output_type: DataType,
+    state: Keyed<Window>,
 }
 
 impl RollingMean {
-    pub fn new(column: String, window:usize) -> Self {
+    pub fn new(
+        column: String,
+        window: usize,
+        partition_by: Option<String>,
+        nulls: NullPolicy,
+        output_type: DataType,
+    ) -> Self {
         Self {
             column,
             window,
-            history: VecDeque::with_capacity(window),
-            sum: 0.0,
+            nulls,
+            output_type,
+            state: Keyed::new(partition_by),
         }
     }
+
+    fn output_name(&self) -> String {
+        format!("{}_rolling_mean_{}", self.column, self.window)
+    }
 }
 
 impl ComputeStage for RollingMean {
-    fn process(&mut self, batch: RecordBatch) -> RecordBatch {
-        let col_idx = batch.schema().index_of(&self.column)
-            .expect("column not found");
+    fn name(&self) -> &'static str {
+        "rolling_mean"
+    }
+
+    fn input_columns(&self) -> Vec<(String, ColumnKind)> {
+        let mut cols = vec![(self.column.clone(), ColumnKind::Numeric)];
+        cols.extend(self.state.input_column());
+        cols
+    }
+
+    fn output_fields(&self) -> Vec<Field> {
+        vec![Field::new(self.output_name(), self.output_type.clone(), true)]
+    }
+
+    fn params(&self) -> serde_json::Value {
+        serde_json::json!({
+            "column": self.column,
+            "window": self.window,
+            "partition_by": self.state.column(),
+            "null_policy": self.nulls.name(),
+            "output_dtype": output_type_name(&self.output_type),
+        })
+    }
 
-        let col = batch.column(col_idx)
-            .as_any()
-            .downcast_ref::<Float64Array>()
-            .expect("column is not f64");
+    fn process(&mut self, batch: RecordBatch) -> Result<RecordBatch, StageError> {
+        let col = f64_column(&batch, &self.column)?;
+        let slots = self.state.slots(&batch)?;
 
         let mut output = Vec::with_capacity(col.len());
 
-        for val in col.values() {
-            self.history.push_back(*val);
-            self.sum += val;
-            if self.history.len() > self.window {
-                self.sum -= self.history.pop_front().unwrap();
+        // col.iter() goes through the validity bitmap so nulls show up as None
+        for (val, slot) in col.iter().zip(slots) {
+            let w = self.state.get_mut(slot);
+            let row = self.nulls.row(val, f64::NAN, &mut w.nulls, Some(self.window));
+            if let Some(v) = row.push {
+                w.push(v, self.window);
             }
-            if self.history.len() == self.window {
-                output.push(self.sum / self.window as f64);
+            if row.null_out || w.history.len() < self.window {
+                output.push(None);
             } else {
-                output.push(f64::NAN);
+                output.push(Some(w.mean(self.window)));
             }
         }
         
-        // build new schema with new column for rol mean
-        let out_col_name = format!("{}_rolling_mean_{}", self.column, self.window);
-        let new_col : ArrayRef = Arc::new(Float64Array::from(output));
-
-        let mut fields: Vec<Field> = batch.schema()
-            .fields()
-            .iter()
-            .map(|f| f.as_ref().clone())
-            .collect();
-        
-        fields.push(Field::new(&out_col_name, DataType::Float64, true));
-
-        let mut columns: Vec<ArrayRef> = batch.columns().to_vec();
-        columns.push(new_col);
-        
-        RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)
-            .expect("failed to build batch")
+        append_column(batch, output, self.output_name(), &self.output_type)
     }
 }
\ No newline at end of file
@@ -0,0 +1,56 @@
+use std::collections::HashSet;
+use arrow::datatypes::{Field, Schema};
+use arrow::record_batch::RecordBatch;
+use crate::compute::{ColumnKind, ComputeStage};
+use crate::error::StageError;
+
+/// keeps only the given columns, in the given order
+///
+/// besides trimming what the sink writes, it tells run() what the rest of the
+/// pipeline doesn't need so parquet sources can skip reading those columns
+pub struct Select {
+    columns: Vec<String>,
+}
+
+impl Select {
+    pub fn new(columns: Vec<String>) -> Self {
+        Self { columns }
+    }
+
+    fn indices(&self, schema: &Schema) -> Result<Vec<usize>, StageError> {
+        self.columns.iter()
+            .map(|c| schema.index_of(c).map_err(|_| StageError::ColumnNotFound(c.clone())))
+            .collect()
+    }
+}
+
+impl ComputeStage for Select {
+    fn name(&self) -> &'static str {
+        "select"
+    }
+
+    fn input_columns(&self) -> Vec<(String, ColumnKind)> {
+        self.columns.iter().map(|c| (c.clone(), ColumnKind::Any)).collect()
+    }
+
+    fn output_fields(&self) -> Vec<Field> {
+        vec![]
+    }
+
+    fn process(&mut self, batch: RecordBatch) -> Result<RecordBatch, StageError> {
+        let indices = self.indices(&batch.schema())?;
+        Ok(batch.project(&indices)?)
+    }
+
+    fn output_schema(&self, input: &Schema) -> Result<Schema, StageError> {
+        Ok(input.project(&self.indices(input)?)?)
+    }
+
+    fn params(&self) -> serde_json::Value {
+        serde_json::json!({ "columns": self.columns })
+    }
+
+    fn required_columns(&self, _downstream: Option<HashSet<String>>) -> Option<HashSet<String>> {
+        Some(self.columns.iter().cloned().collect())
+    }
+}
\ No newline at end of file
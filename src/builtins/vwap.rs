@@ -1,61 +1,267 @@
 use std::collections::VecDeque;
-use arrow::array::Float64Array;
+use arrow::datatypes::{DataType, Field};
 use arrow::record_batch::RecordBatch;
-use crate::compute::ComputeStage;
-use crate::builtins::zscore::append_column;
+use crate::compute::{ColumnKind, ComputeStage};
+use crate::error::StageError;
+use crate::builtins::keyed::Keyed;
+use crate::builtins::nulls::{NullPolicy, NullState};
+use crate::builtins::zscore::{append_column, f64_column, output_type_name};
+
+/// neumaier compensated sum, keeps add/remove of big and small
+/// numbers from eating the low bits of a running total
+#[derive(Default, Clone, Copy)]
+struct Sum {
+    sum: f64,
+    comp: f64,
+}
+
+impl Sum {
+    fn add(&mut self, x: f64) {
+        let t = self.sum + x;
+        if self.sum.abs() >= x.abs() {
+            self.comp += (self.sum - t) + x;
+        } else {
+            self.comp += (x - t) + self.sum;
+        }
+        self.sum = t;
+    }
+
+    fn value(&self) -> f64 {
+        self.sum + self.comp
+    }
+}
+
+/// vwap window for one partition with running pv and v sums, O(1) per row
+///
+/// sums are recomputed from the window every `window` slides so rounding
+/// can't build up, in between results match the plain fold to ~1e-12 relative.
+/// NaN/inf in the window falls back to the plain fold so it behaves exactly like before
+#[derive(Default)]
+struct Window {
+    // store (price * volume, volume) pairs
+    history: VecDeque<(f64, f64)>,
+    pv: Sum,
+    v: Sum,
+    // volumes != 0 in the window, when this is 0 v_sum is exactly 0 and the output is null
+    nonzero: usize,
+    // pairs with a NaN/inf in them
+    non_finite: usize,
+    since_anchor: usize,
+    // (price, volume) null bookkeeping, a row is null if either side is
+    nulls: NullState<(f64, f64)>,
+}
+
+impl Window {
+    fn push(&mut self, pv: f64, v: f64, window: usize) {
+        self.history.push_back((pv, v));
+        self.track(pv, v, 1);
+        if self.history.len() <= window {
+            return;
+        }
+
+        let (old_pv, old_v) = self.history.pop_front().unwrap();
+        self.track(old_pv, old_v, -1);
+        self.since_anchor += 1;
+
+        // rebuild every `window` slides, or as soon as the last NaN/inf is gone
+        let bad_left = !(old_pv.is_finite() && old_v.is_finite()) && self.non_finite == 0;
+        if self.since_anchor >= window || bad_left {
+            self.anchor();
+        }
+    }
+
+    /// adds (sign 1) or removes (sign -1) a pair from the running totals
+    fn track(&mut self, pv: f64, v: f64, sign: isize) {
+        if v != 0.0 {
+            self.nonzero = self.nonzero.wrapping_add_signed(sign);
+        }
+        if !pv.is_finite() || !v.is_finite() {
+            self.non_finite = self.non_finite.wrapping_add_signed(sign);
+            return;
+        }
+        self.pv.add(sign as f64 * pv);
+        self.v.add(sign as f64 * v);
+    }
+
+    fn anchor(&mut self) {
+        let (pv, v) = fold(&self.history);
+        self.pv = Sum { sum: pv, comp: 0.0 };
+        self.v = Sum { sum: v, comp: 0.0 };
+        self.since_anchor = 0;
+    }
+
+    /// only valid once the window is full, None when there's no volume to divide by
+    fn vwap(&self) -> Option<f64> {
+        let (pv_sum, v_sum) = if self.non_finite > 0 {
+            fold(&self.history)
+        } else if self.nonzero == 0 {
+            return None;
+        } else {
+            (self.pv.value(), self.v.value())
+        };
+        if v_sum == 0.0 { None } else { Some(pv_sum / v_sum) }
+    }
+}
+
+/// the plain O(window) sums
+fn fold(history: &VecDeque<(f64, f64)>) -> (f64, f64) {
+    history.iter()
+        .fold((0.0, 0.0), |(pv, v), (pvi, vi)| (pv + pvi, v + vi))
+}
 
 pub struct Vwap {
     price_col: String,
     volume_col: String,
     window: usize,
-    // store (price * volume, volume) pairs
-    history: VecDeque<(f64, f64)>,
+    nulls: NullPolicy,
+    output_type: DataType,
+    // one window per partition
+    history: Keyed<Window>,
 }
 
 impl Vwap {
-    pub fn new(price_col: String, volume_col: String, window: usize) -> Self {
+    pub fn new(
+        price_col: String,
+        volume_col: String,
+        window: usize,
+        partition_by: Option<String>,
+        nulls: NullPolicy,
+        output_type: DataType,
+    ) -> Self {
         Self {
             price_col,
             volume_col,
             window,
-            history: VecDeque::with_capacity(window),
+            nulls,
+            output_type,
+            history: Keyed::new(partition_by),
         }
     }
+
+    fn output_name(&self) -> String {
+        format!("vwap_{}", self.window)
+    }
 }
 
 impl ComputeStage for Vwap {
-    fn process(&mut self, batch: RecordBatch) -> RecordBatch {
-        let price_idx = batch.schema().index_of(&self.price_col)
-            .expect("price column not found");
-        let volume_idx = batch.schema().index_of(&self.volume_col)
-            .expect("volume column not found");
-
-        let prices = batch.column(price_idx)
-            .as_any().downcast_ref::<Float64Array>()
-            .expect("price column is not f64");
-        let volumes = batch.column(volume_idx)
-            .as_any().downcast_ref::<Float64Array>()
-            .expect("volume column is not f64");
+    fn name(&self) -> &'static str {
+        "vwap"
+    }
+
+    fn input_columns(&self) -> Vec<(String, ColumnKind)> {
+        let mut cols = vec![
+            (self.price_col.clone(), ColumnKind::Numeric),
+            (self.volume_col.clone(), ColumnKind::Numeric),
+        ];
+        cols.extend(self.history.input_column());
+        cols
+    }
+
+    fn output_fields(&self) -> Vec<Field> {
+        vec![Field::new(self.output_name(), self.output_type.clone(), true)]
+    }
+
+    fn params(&self) -> serde_json::Value {
+        serde_json::json!({
+            "price_col": self.price_col,
+            "volume_col": self.volume_col,
+            "window": self.window,
+            "partition_by": self.history.column(),
+            "null_policy": self.nulls.name(),
+            "output_dtype": output_type_name(&self.output_type),
+        })
+    }
+
+    fn process(&mut self, batch: RecordBatch) -> Result<RecordBatch, StageError> {
+        let prices = f64_column(&batch, &self.price_col)?;
+        let volumes = f64_column(&batch, &self.volume_col)?;
+        let slots = self.history.slots(&batch)?;
 
         let mut output = Vec::with_capacity(batch.num_rows());
 
-        for (price, volume) in prices.values().iter().zip(volumes.values().iter()) {
-            self.history.push_back((price * volume, *volume));
-            if self.history.len() > self.window {
-                self.history.pop_front();
+        for ((price, volume), slot) in prices.iter().zip(volumes.iter()).zip(slots) {
+            let window = self.history.get_mut(slot);
+            let pair = price.zip(volume);
+            let row = self.nulls.row(pair, (f64::NAN, f64::NAN), &mut window.nulls, Some(self.window));
+            if let Some((price, volume)) = row.push {
+                window.push(price * volume, volume, self.window);
             }
 
-            if self.history.len() < self.window {
-                output.push(f64::NAN);
+            if row.null_out || window.history.len() < self.window {
+                output.push(None);
                 continue;
             }
 
-            let (pv_sum, v_sum) = self.history.iter()
-                .fold((0.0, 0.0), |(pv, v), (pvi, vi)| (pv + pvi, v + vi));
+            output.push(window.vwap());
+        }
+
+        append_column(batch, output, self.output_name(), &self.output_type)
+    }
+}
 
-            output.push(if v_sum == 0.0 { f64::NAN } else { pv_sum / v_sum });
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a random walk of whole ticks with volumes from 0.5 to 5000, xorshift so it's the same every run
+    fn trades(start: f64, tick: f64, n: usize) -> Vec<(f64, f64)> {
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        let mut price = start;
+        (0..n).map(|_| {
+            price += ((next() % 21) as f64 - 10.0) * tick;
+            (price, (next() % 10_000 + 1) as f64 * 0.5)
+        }).collect()
+    }
+
+    /// worst relative difference from the vwap summed from scratch on every window
+    fn worst_error(trades: &[(f64, f64)], window: usize) -> f64 {
+        let mut w = Window::default();
+        let mut worst: f64 = 0.0;
+        for &(price, volume) in trades {
+            w.push(price * volume, volume, window);
+            if w.history.len() < window {
+                continue;
+            }
+            let pv: f64 = w.history.iter().map(|(pv, _)| pv).sum();
+            let v: f64 = w.history.iter().map(|(_, v)| v).sum();
+            let expected = pv / v;
+            worst = worst.max(((w.vwap().unwrap() - expected) / expected).abs());
+        }
+        worst
+    }
+
+    #[test]
+    fn matches_from_scratch_at_100() {
+        let trades = trades(100.0, 0.01, 20_000);
+        for window in [1, 2, 20, 500] {
+            let err = worst_error(&trades, window);
+            assert!(err < 1e-12, "window {}: off by {:e}", window, err);
         }
+    }
 
-        append_column(batch, output, format!("vwap_{}", self.window))
+    #[test]
+    fn matches_from_scratch_at_50000() {
+        let trades = trades(50_000.0, 0.0001, 20_000);
+        for window in [1, 2, 20, 500] {
+            let err = worst_error(&trades, window);
+            assert!(err < 1e-12, "window {}: off by {:e}", window, err);
+        }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn no_volume_is_null() {
+        let mut w = Window::default();
+        for _ in 0..3 {
+            w.push(0.0, 0.0, 3);
+        }
+        assert_eq!(w.vwap(), None);
+        w.push(101.0 * 2.0, 2.0, 3);
+        assert_eq!(w.vwap(), Some(101.0));
+    }
+}
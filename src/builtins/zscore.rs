@@ -1,80 +1,341 @@
 use std::collections::VecDeque;
 use std::sync::Arc;
-use arrow::array::{ArrayRef, Float64Array};
-use arrow::datatypes::{DataType, Field, Schema};
+use arrow::array::{ArrayRef, AsArray, Float64Array};
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, Field, Float64Type, Schema};
 use arrow::record_batch::RecordBatch;
-use crate::compute::ComputeStage;
+use pyo3::prelude::*;
+use pyo3::exceptions::PyValueError;
+use crate::compute::{ColumnKind, ComputeStage};
+use crate::error::StageError;
+use crate::builtins::keyed::Keyed;
+use crate::builtins::nulls::{NullPolicy, NullState};
+
+/// rolling window for one partition with running sums so each row is O(1) instead of O(lookback)
+///
+/// the sums are of x - shift, where shift is the window mean as of the last recompute. a
+/// sliding mean picks up rounding relative to the price, 50000 with 0.0001 ticks loses most of
+/// its digits that way. deviations from a nearby value stay about as small as the ticks
+/// whatever the price level, so the rounding stays relative to the spread. the sums are
+/// recomputed from the window every `lookback` slides (amortized O(1)) to keep shift close
+/// to the mean. constant windows still give exactly 0 and any NaN/inf in the window falls
+/// back to the exact two pass result
+#[derive(Default)]
+struct Window {
+    history: VecDeque<f64>,
+    shift: f64,
+    // sum of x - shift and of (x - shift)^2 over the window
+    sum: f64,
+    sumsq: f64,
+    // slides since the sums were last recomputed from scratch
+    since_anchor: usize,
+    // how many of the newest values are identical, a full window of them means std is exactly 0
+    run: usize,
+    // NaN/inf values currently in the window, these would poison the sums for good
+    non_finite: usize,
+    nulls: NullState<f64>,
+}
+
+impl Window {
+    fn push(&mut self, x: f64, lookback: usize) {
+        self.run = match self.history.back() {
+            Some(last) if *last == x => self.run + 1,
+            _ => 1,
+        };
+        self.history.push_back(x);
+        if !x.is_finite() {
+            self.non_finite += 1;
+        }
+
+        if self.history.len() <= lookback {
+            if self.history.len() == 1 {
+                self.shift = x;
+            }
+            if self.non_finite == 0 {
+                // still filling, shifted by the first value until the window is full
+                let d = x - self.shift;
+                self.sum += d;
+                self.sumsq += d * d;
+                if self.history.len() == lookback {
+                    self.anchor();
+                }
+            }
+            return;
+        }
+
+        let old = self.history.pop_front().unwrap();
+        if !old.is_finite() {
+            self.non_finite -= 1;
+            if self.non_finite == 0 {
+                // last bad value just left, running sums are garbage so start over
+                self.anchor();
+            }
+            return;
+        }
+        if self.non_finite > 0 {
+            return;
+        }
+
+        // window size stays the same, swap old out for x
+        let (new, old) = (x - self.shift, old - self.shift);
+        self.sum += new - old;
+        self.sumsq += (new - old) * (new + old);
+
+        self.since_anchor += 1;
+        if self.since_anchor >= lookback {
+            self.anchor();
+        }
+    }
+
+    /// recompute the sums exactly from the window, shifted by its mean
+    fn anchor(&mut self) {
+        let (shift, offset, _) = two_pass(&self.history);
+        let mean = shift + offset;
+        self.shift = mean;
+        self.sum = self.history.iter().map(|x| x - mean).sum();
+        self.sumsq = self.history.iter().map(|x| (x - mean).powi(2)).sum();
+        self.since_anchor = 0;
+    }
+
+    /// z score of x, only valid once the window is full
+    fn zscore(&self, x: f64, lookback: usize) -> f64 {
+        let n = lookback as f64;
+        let (deviation, m2) = if self.non_finite > 0 {
+            let (shift, offset, m2) = two_pass(&self.history);
+            ((x - shift) - offset, m2)
+        } else if self.run >= lookback && lookback > 1 {
+            // every value in the window is the same
+            return 0.0;
+        } else {
+            // x - mean without ever forming the mean, which would round at the price's scale
+            ((x - self.shift) - self.sum / n, (self.sumsq - self.sum * self.sum / n).max(0.0))
+        };
+        let std = (m2 / (lookback - 1) as f64).sqrt();
+        if std == 0.0 { 0.0 } else { deviation / std }
+    }
+}
+
+/// mean and sum of squared deviations the slow exact way, as (shift, mean - shift, m2)
+///
+/// everything is taken relative to the first value, x - shift is exact for nearby values so
+/// no rounding at the price's scale gets in. the mean is only ever formed by the caller
+fn two_pass(history: &VecDeque<f64>) -> (f64, f64, f64) {
+    let shift = history[0];
+    let offset = history.iter().map(|x| x - shift).sum::<f64>() / history.len() as f64;
+    let m2 = history.iter()
+        .map(|x| ((x - shift) - offset).powi(2))
+        .sum::<f64>();
+    (shift, offset, m2)
+}
 
 pub struct ZScore {
     column: String,
     // window size
     lookback: usize,
+    nulls: NullPolicy,
+    output_type: DataType,
 
-    // rolling window of raw vals of size lookback
-    history: VecDeque<f64>,
+    // rolling window of raw vals of size lookback, one per partition
+    history: Keyed<Window>,
 }
 
 impl ZScore {
-    pub fn new(column: String, lookback: usize) -> Self {
+    pub fn new(
+        column: String,
+        lookback: usize,
+        partition_by: Option<String>,
+        nulls: NullPolicy,
+        output_type: DataType,
+    ) -> Self {
         Self {
             column,
             lookback,
-            history: VecDeque::with_capacity(lookback),
+            nulls,
+            output_type,
+            history: Keyed::new(partition_by),
         }
     }
+
+    fn output_name(&self) -> String {
+        format!("{}_zscore_{}", self.column, self.lookback)
+    }
 }
 
 impl ComputeStage for ZScore {
+    fn name(&self) -> &'static str {
+        "zscore"
+    }
+
+    fn input_columns(&self) -> Vec<(String, ColumnKind)> {
+        let mut cols = vec![(self.column.clone(), ColumnKind::Numeric)];
+        cols.extend(self.history.input_column());
+        cols
+    }
+
+    fn output_fields(&self) -> Vec<Field> {
+        vec![Field::new(self.output_name(), self.output_type.clone(), true)]
+    }
+
+    fn params(&self) -> serde_json::Value {
+        serde_json::json!({
+            "column": self.column,
+            "lookback": self.lookback,
+            "partition_by": self.history.column(),
+            "null_policy": self.nulls.name(),
+            "output_dtype": output_type_name(&self.output_type),
+        })
+    }
+
     // this is similar to the other builtins, downcast to get direct slices of arrow buffers, etc.
-    fn process(&mut self, batch: RecordBatch) -> RecordBatch {
-        let col_idx = batch.schema().index_of(&self.column)
-            .expect("column not found");
-        let col = batch.column(col_idx)
-            .as_any()
-            .downcast_ref::<Float64Array>()
-            .expect("column is not f64");
+    fn process(&mut self, batch: RecordBatch) -> Result<RecordBatch, StageError> {
+        let col = f64_column(&batch, &self.column)?;
+        let slots = self.history.slots(&batch)?;
 
         let mut output = Vec::with_capacity(col.len());
 
-        for val in col.values() {
-            self.history.push_back(*val);
-            if self.history.len() > self.lookback {
-                self.history.pop_front();
-            }
+        for (val, slot) in col.iter().zip(slots) {
+            let window = self.history.get_mut(slot);
+            let row = self.nulls.row(val, f64::NAN, &mut window.nulls, Some(self.lookback));
+            let Some(val) = row.push else {
+                output.push(None);
+                continue;
+            };
+            window.push(val, self.lookback);
 
-            if self.history.len() < self.lookback {
-                output.push(f64::NAN);
+            if row.null_out || window.history.len() < self.lookback {
+                output.push(None);
                 continue;
             }
 
-            // this is O(lookback) per row right now
-            // for small lookbacks that fine
-            // but for large maybe 
-            // TODO: implement large lookbck algorithm for o(1)
-            let mean = self.history.iter().sum::<f64>() / self.lookback as f64;
-            let variance = self.history.iter()
-                .map(|x| (x - mean).powi(2))
-                .sum::<f64>() / (self.lookback - 1) as f64;
-            let std = variance.sqrt();
-
-            output.push(if std == 0.0 { 0.0 } else { (val - mean) / std });
+            output.push(Some(window.zscore(val, self.lookback)));
         }
 
-        append_column(batch, output, format!("{}_zscore_{}", self.column, self.lookback))
+        append_column(batch, output, self.output_name(), &self.output_type)
     }
 }
 
-/// appends f64 column to exisitng arrow recordbatch
-pub fn append_column(batch: RecordBatch, values: Vec<f64>, name: String) -> RecordBatch {
-    let new_col: ArrayRef = Arc::new(Float64Array::from(values));
+/// looks up a column by name and gets it as f64
+///
+/// f64 columns are just downcast, other numeric types (ints, float32, decimal128)
+/// go through arrow's cast kernel which keeps the validity bitmap.
+/// errors instead of panicking so a typo'd column name fails the pipeline cleanly
+pub fn f64_column(batch: &RecordBatch, name: &str) -> Result<Float64Array, StageError> {
+    let idx = batch.schema().index_of(name)
+        .map_err(|_| StageError::ColumnNotFound(name.to_string()))?;
+    let col = batch.column(idx);
+    if let Some(f) = col.as_any().downcast_ref::<Float64Array>() {
+        return Ok(f.clone());
+    }
+    if !ColumnKind::Numeric.accepts(col.data_type()) {
+        return Err(StageError::WrongType {
+            column: name.to_string(),
+            expected: ColumnKind::Numeric.describe(),
+            found: col.data_type().clone(),
+        });
+    }
+    Ok(cast(col, &DataType::Float64)?.as_primitive::<Float64Type>().clone())
+}
+
+/// parses output_dtype, builtins compute in f64 and can narrow to f32 for smaller files
+pub fn parse_output_type(s: &str) -> PyResult<DataType> {
+    match s {
+        "float64" => Ok(DataType::Float64),
+        "float32" => Ok(DataType::Float32),
+        other => Err(PyValueError::new_err(format!(
+            "unknown output_dtype '{}', expected 'float64' or 'float32'", other
+        ))),
+    }
+}
+
+/// back to the output_dtype= string
+pub fn output_type_name(data_type: &DataType) -> &'static str {
+    match data_type {
+        DataType::Float32 => "float32",
+        _ => "float64",
+    }
+}
+
+/// appends f64 column to exisitng arrow recordbatch, cast to `data_type` (float64 or float32)
+/// None values become nulls in the validity bitmap
+pub fn append_column(
+    batch: RecordBatch,
+    values: Vec<Option<f64>>,
+    name: String,
+    data_type: &DataType,
+) -> Result<RecordBatch, StageError> {
+    let mut new_col: ArrayRef = Arc::new(Float64Array::from(values));
+    if data_type != &DataType::Float64 {
+        new_col = cast(&new_col, data_type)?;
+    }
     let mut fields: Vec<Field> = batch.schema().fields().iter()
         .map(|f| f.as_ref().clone())
         .collect();
-    fields.push(Field::new(&name, DataType::Float64, true));
+    fields.push(Field::new(&name, data_type.clone(), true));
 
     let mut columns: Vec<ArrayRef> = batch.columns().to_vec();
     columns.push(new_col);
 
-    RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)
-        .expect("failed to build output batch")
-}
\ No newline at end of file
+    Ok(RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a random walk of whole ticks, xorshift so it's the same every run
+    fn walk(start: f64, tick: f64, n: usize) -> Vec<f64> {
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut price = start;
+        (0..n).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            price += ((state % 21) as f64 - 10.0) * tick;
+            price
+        }).collect()
+    }
+
+    /// worst difference from the z score computed from scratch on every window
+    fn worst_error(prices: &[f64], lookback: usize) -> f64 {
+        let mut window = Window::default();
+        let mut worst: f64 = 0.0;
+        for &x in prices {
+            window.push(x, lookback);
+            if window.history.len() < lookback {
+                continue;
+            }
+            let (shift, offset, m2) = two_pass(&window.history);
+            let expected = ((x - shift) - offset) / (m2 / (lookback - 1) as f64).sqrt();
+            worst = worst.max((window.zscore(x, lookback) - expected).abs());
+        }
+        worst
+    }
+
+    #[test]
+    fn matches_two_pass_at_100() {
+        let prices = walk(100.0, 0.01, 20_000);
+        for lookback in [2, 20, 500] {
+            let err = worst_error(&prices, lookback);
+            assert!(err < 1e-12, "lookback {}: off by {:e}", lookback, err);
+        }
+    }
+
+    /// the spread is ~1e-8 of the price, a sliding mean used to lose most of the digits here
+    #[test]
+    fn matches_two_pass_at_50000() {
+        let prices = walk(50_000.0, 0.0001, 20_000);
+        for lookback in [2, 20, 500] {
+            let err = worst_error(&prices, lookback);
+            assert!(err < 1e-12, "lookback {}: off by {:e}", lookback, err);
+        }
+    }
+
+    #[test]
+    fn constant_window_is_zero() {
+        let mut window = Window::default();
+        for _ in 0..10 {
+            window.push(50_000.1, 5);
+        }
+        assert_eq!(window.zscore(50_000.1, 5), 0.0);
+    }
+}
@@ -0,0 +1,144 @@
+use arrow::datatypes::SchemaRef;
+use arrow::ffi_stream::ArrowArrayStreamReader;
+use arrow::pyarrow::{FromPyArrow, ToPyArrow};
+use arrow::record_batch::RecordBatch;
+use pyo3::prelude::*;
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::types::{PyDict, PyList};
+use crate::error::StageError;
+
+/// how a python callback (sink or py_transform) gets the data
+///
+/// row    - one dict per row, simple but every value becomes a python object
+/// batch  - one pyarrow.RecordBatch per batch, no copy
+/// numpy  - one {column: numpy array} dict per batch, no copy for numeric columns without nulls
+/// polars - one polars.DataFrame per batch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallbackMode {
+    Row,
+    Batch,
+    Numpy,
+    Polars,
+}
+
+impl CallbackMode {
+    pub fn parse(s: &str) -> PyResult<Self> {
+        match s {
+            "row" => Ok(CallbackMode::Row),
+            "batch" => Ok(CallbackMode::Batch),
+            "numpy" => Ok(CallbackMode::Numpy),
+            "polars" => Ok(CallbackMode::Polars),
+            other => Err(PyValueError::new_err(format!(
+                "unknown mode '{}', expected 'row', 'batch', 'numpy' or 'polars'", other
+            ))),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CallbackMode::Row => "row",
+            CallbackMode::Batch => "batch",
+            CallbackMode::Numpy => "numpy",
+            CallbackMode::Polars => "polars",
+        }
+    }
+
+    /// the batch as what the callback expects, a list of row dicts for Row
+    pub fn convert<'py>(&self, py: Python<'py>, batch: &RecordBatch) -> PyResult<Bound<'py, PyAny>> {
+        let py_batch = batch.to_pyarrow(py)?;
+        match self {
+            CallbackMode::Row => py_batch.call_method0("to_pylist"),
+            CallbackMode::Batch => Ok(py_batch),
+            CallbackMode::Numpy => {
+                let columns = PyDict::new(py);
+                for (i, field) in batch.schema().fields().iter().enumerate() {
+                    let column = py_batch.call_method1("column", (i,))?;
+                    let kwargs = PyDict::new(py);
+                    kwargs.set_item("zero_copy_only", false)?;
+                    columns.set_item(field.name(), column.call_method("to_numpy", (), Some(&kwargs))?)?;
+                }
+                Ok(columns.into_any())
+            }
+            CallbackMode::Polars => py.import("polars")?.call_method1("from_arrow", (py_batch,)),
+        }
+    }
+}
+
+/// runs a py_transform callback on one batch and turns what it returned back into batches
+///
+/// row mode calls it per row and drops rows it returns None for. the other modes call it
+/// once and take a RecordBatch, a {column: array/list} dict, anything with __arrow_c_stream__
+/// (pyarrow.Table, polars.DataFrame) or None to drop the batch. `schema` is the declared
+/// output schema, dicts and rows get built with it so their types line up
+pub fn call_transform(
+    py: Python<'_>,
+    cb: &Py<PyAny>,
+    mode: CallbackMode,
+    schema: Option<&SchemaRef>,
+    batch: &RecordBatch,
+) -> Result<Vec<RecordBatch>, StageError> {
+    let data = mode.convert(py, batch)?;
+    let py_schema = schema.map(|s| s.to_pyarrow(py)).transpose()?;
+    let kwargs = PyDict::new(py);
+    if let Some(py_schema) = &py_schema {
+        kwargs.set_item("schema", py_schema)?;
+    }
+    let record_batch = py.import("pyarrow")?.getattr("RecordBatch")?;
+
+    if mode == CallbackMode::Row {
+        // returning None from the callback drops the row
+        let mut results: Vec<Bound<'_, PyAny>> = Vec::new();
+        for row in data.cast::<PyList>().map_err(PyErr::from)?.iter() {
+            let result = cb.bind(py).call1((row,))?;
+            if !result.is_none() {
+                results.push(result);
+            }
+        }
+        if results.is_empty() {
+            return Ok(vec![]);
+        }
+        let rows = PyList::new(py, &results)?;
+        let out = record_batch.call_method("from_pylist", (rows,), Some(&kwargs))?;
+        return Ok(vec![RecordBatch::from_pyarrow_bound(&out)?]);
+    }
+
+    let out = cb.bind(py).call1((data,))?;
+    if out.is_none() {
+        return Ok(vec![]);
+    }
+    if out.is_instance_of::<PyDict>() {
+        let out = record_batch.call_method("from_pydict", (out,), Some(&kwargs))?;
+        return Ok(vec![RecordBatch::from_pyarrow_bound(&out)?]);
+    }
+    batches_from_py(&out)
+}
+
+/// a RecordBatch (or anything with __arrow_c_array__) or a whole stream (__arrow_c_stream__,
+/// pyarrow.Table, polars.DataFrame, RecordBatchReader...) as rust batches
+pub fn batches_from_py(obj: &Bound<'_, PyAny>) -> Result<Vec<RecordBatch>, StageError> {
+    if obj.hasattr("__arrow_c_array__")? {
+        return Ok(vec![RecordBatch::from_pyarrow_bound(obj)?]);
+    }
+    if obj.hasattr("__arrow_c_stream__")? {
+        let reader = ArrowArrayStreamReader::from_pyarrow_bound(obj)?;
+        return Ok(reader.collect::<Result<Vec<_>, _>>()?);
+    }
+    Err(PyTypeError::new_err(format!(
+        "expected a pyarrow.RecordBatch, a dict of columns, a table / dataframe or None, got {}",
+        obj.get_type().name()?
+    )).into())
+}
+
+/// every batch a py_transform returns has to have the same columns and types,
+/// the declared schema= or else the first batch's
+pub fn check_output(expected: &SchemaRef, batch: &RecordBatch) -> Result<(), StageError> {
+    let describe = |s: &SchemaRef| s.fields().iter()
+        .map(|f| format!("{}: {}", f.name(), f.data_type()))
+        .collect::<Vec<_>>();
+    if describe(expected) != describe(&batch.schema()) {
+        return Err(StageError::Output(format!(
+            "returned columns {:?}, expected {:?}", describe(&batch.schema()), describe(expected)
+        )));
+    }
+    Ok(())
+}
\ No newline at end of file
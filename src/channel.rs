@@ -0,0 +1,57 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use arrow::record_batch::RecordBatch;
+use crossbeam_channel::Sender;
+
+/// shared by every thread of one run, set by PipelineHandle.cancel() or ctrl-c during run()
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// batches and rows a stage has sent on, read live by PipelineHandle.progress()
+#[derive(Debug, Default)]
+pub struct Progress {
+    pub batches: AtomicUsize,
+    pub rows: AtomicUsize,
+}
+
+/// the sending end of a stage's output channel
+///
+/// counts what goes through. a source's output also refuses batches once the run is
+/// cancelled, the same way a hung up receiver does. the source stops like it would for that,
+/// its channel closes and the stages after it finish what's in flight (sinks close their files)
+pub struct Output {
+    sender: Sender<RecordBatch>,
+    /// None for everything but sources, they pass on whatever reaches them
+    cancel: Option<CancelToken>,
+    progress: Arc<Progress>,
+}
+
+impl Output {
+    pub fn new(sender: Sender<RecordBatch>, cancel: Option<CancelToken>, progress: Arc<Progress>) -> Self {
+        Self { sender, cancel, progress }
+    }
+
+    /// false means stop, downstream hung up or the pipeline was cancelled
+    pub fn send(&self, batch: RecordBatch) -> bool {
+        if self.cancel.as_ref().is_some_and(CancelToken::is_cancelled) {
+            return false;
+        }
+        let rows = batch.num_rows();
+        if self.sender.send(batch).is_err() {
+            return false;
+        }
+        self.progress.batches.fetch_add(1, Ordering::Relaxed);
+        self.progress.rows.fetch_add(rows, Ordering::Relaxed);
+        true
+    }
+}
\ No newline at end of file
@@ -1,5 +1,99 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use arrow::datatypes::{DataType, Field, Schema};
 use arrow::record_batch::RecordBatch;
+use crate::error::StageError;
+
+/// what a stage needs an input column to be
+pub enum ColumnKind {
+    /// any int, uint, float or decimal128 column, builtins cast it to f64
+    Numeric,
+    /// partition key, any string column or dictionary of strings
+    Key,
+    /// anything, the stage just passes the column along (select)
+    Any,
+}
+
+impl ColumnKind {
+    pub fn accepts(&self, data_type: &DataType) -> bool {
+        match self {
+            ColumnKind::Numeric => data_type.is_integer()
+                || data_type.is_floating()
+                || matches!(data_type, DataType::Decimal128(_, _)),
+            ColumnKind::Key => match data_type {
+                DataType::Dictionary(_, values) => is_string(values),
+                other => is_string(other),
+            },
+            ColumnKind::Any => true,
+        }
+    }
+
+    /// for error messages
+    pub fn describe(&self) -> &'static str {
+        match self {
+            ColumnKind::Numeric => "a numeric type (Int*, UInt*, Float*, Decimal128)",
+            ColumnKind::Key => "Utf8 or Dictionary(_, Utf8)",
+            ColumnKind::Any => "any type",
+        }
+    }
+}
+
+fn is_string(data_type: &DataType) -> bool {
+    matches!(data_type, DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View)
+}
 
 pub trait ComputeStage: Send + Sync {
-    fn process(&mut self, batch: RecordBatch) -> RecordBatch;
+    /// short name used in error messages, e.g. "rolling_mean"
+    fn name(&self) -> &'static str;
+
+    /// columns this stage reads and the type it needs them to be
+    fn input_columns(&self) -> Vec<(String, ColumnKind)>;
+
+    /// fields this stage appends to every batch
+    fn output_fields(&self) -> Vec<Field>;
+
+    fn process(&mut self, batch: RecordBatch) -> Result<RecordBatch, StageError>;
+
+    /// checks the declared inputs against the upstream schema and returns
+    /// the schema this stage will emit, so bad pipelines fail at construction
+    /// instead of halfway through a file
+    fn output_schema(&self, input: &Schema) -> Result<Schema, StageError> {
+        for (column, kind) in self.input_columns() {
+            let field = input.field_with_name(&column)
+                .map_err(|_| StageError::ColumnNotFound(column.clone()))?;
+            if !kind.accepts(field.data_type()) {
+                return Err(StageError::WrongType {
+                    column,
+                    expected: kind.describe(),
+                    found: field.data_type().clone(),
+                });
+            }
+        }
+
+        let mut fields = input.fields().to_vec();
+        for field in self.output_fields() {
+            if fields.iter().any(|f| f.name() == field.name()) {
+                return Err(StageError::DuplicateColumn(field.name().clone()));
+            }
+            fields.push(Arc::new(field));
+        }
+        // matches append_column, which doesn't carry schema metadata over
+        Ok(Schema::new(fields))
+    }
+
+    /// columns this stage needs from upstream given what everything after it needs
+    /// (None means every column), run() uses this to only read those from parquet
+    fn required_columns(&self, downstream: Option<HashSet<String>>) -> Option<HashSet<String>> {
+        let mut needed = downstream?;
+        for field in self.output_fields() {
+            needed.remove(field.name());
+        }
+        needed.extend(self.input_columns().into_iter().map(|(column, _)| column));
+        Some(needed)
+    }
+
+    /// the arguments the stage was built with, written to parquet footers as lineage
+    fn params(&self) -> serde_json::Value {
+        serde_json::json!({})
+    }
 }
\ No newline at end of file
@@ -0,0 +1,197 @@
+use std::fmt;
+use arrow::datatypes::DataType;
+use arrow::error::ArrowError;
+use parquet::errors::ParquetError;
+use pyo3::prelude::*;
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+
+// raised from Pipeline.run() when any stage fails
+pyo3::create_exception!(otters, PipelineError, PyRuntimeError);
+
+// raised while building the pipeline when a stage doesn't fit the upstream schema
+pyo3::create_exception!(otters, SchemaError, PyValueError);
+
+/// everything that can go wrong inside a single stage
+///
+/// returned from ComputeStage::process and from the source/sink threads
+#[derive(Debug)]
+pub enum StageError {
+    /// stage asked for a column the batch doesn't have
+    ColumnNotFound(String),
+    /// column exists but isn't a type the stage can work with
+    WrongType { column: String, expected: &'static str, found: DataType },
+    /// stage would add a column that already exists
+    DuplicateColumn(String),
+    /// same column has incompatible types in two inputs (multi file sources)
+    Mismatch { column: String, expected: DataType, found: DataType },
+    /// arrow itself failed, building output batches/schemas, casts or parsing csv
+    Schema(ArrowError),
+    Io(std::io::Error),
+    Parquet(ParquetError),
+    Postgres(postgres::Error),
+    /// query returns a postgres type there's no arrow mapping for
+    UnsupportedPgType { column: String, pg_type: String },
+    /// python callback, generator or conversion raised
+    Python(PyErr),
+    /// a python source yielded a row that doesn't fit its schema=, row counts from the start
+    Row { row: usize, message: String },
+    /// hive layout doesn't line up, files with different partition keys or
+    /// batches changing schema halfway through a partitioned sink
+    Partition(String),
+    /// a py_transform returned batches that don't match its schema
+    Output(String),
+    /// any of the above, for a source that reads more than one file
+    File { path: String, error: Box<StageError> },
+}
+
+impl fmt::Display for StageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StageError::ColumnNotFound(col) => write!(f, "column '{}' not found", col),
+            StageError::WrongType { column, expected, found } => {
+                write!(f, "column '{}' has type {}, expected {}", column, found, expected)
+            }
+            StageError::DuplicateColumn(col) => write!(f, "output column '{}' already exists", col),
+            StageError::Mismatch { column, expected, found } => {
+                write!(f, "column '{}' has type {}, earlier files have {}", column, found, expected)
+            }
+            StageError::Schema(e) => write!(f, "arrow error: {}", e),
+            StageError::Io(e) => write!(f, "io error: {}", e),
+            StageError::Parquet(e) => write!(f, "parquet error: {}", e),
+            // the top level postgres error is just "db error", the detail is in the source
+            StageError::Postgres(e) => match std::error::Error::source(e) {
+                Some(cause) => write!(f, "postgres error: {}: {}", e, cause),
+                None => write!(f, "postgres error: {}", e),
+            },
+            StageError::UnsupportedPgType { column, pg_type } => write!(
+                f, "postgres column '{}' has type {} which can't be read, cast it in the query (::float8, ::text...)",
+                column, pg_type
+            ),
+            StageError::Python(e) => write!(f, "python error: {}", e),
+            StageError::Row { row, message } => write!(f, "row {} {}", row, message),
+            StageError::Partition(msg) => write!(f, "partition error: {}", msg),
+            StageError::Output(msg) => write!(f, "bad py_transform output: {}", msg),
+            StageError::File { path, error } => write!(f, "{}: {}", path, error),
+        }
+    }
+}
+
+impl std::error::Error for StageError {}
+
+impl From<ArrowError> for StageError {
+    fn from(e: ArrowError) -> Self {
+        StageError::Schema(e)
+    }
+}
+
+impl From<std::io::Error> for StageError {
+    fn from(e: std::io::Error) -> Self {
+        StageError::Io(e)
+    }
+}
+
+impl From<ParquetError> for StageError {
+    fn from(e: ParquetError) -> Self {
+        StageError::Parquet(e)
+    }
+}
+
+impl From<postgres::Error> for StageError {
+    fn from(e: postgres::Error) -> Self {
+        StageError::Postgres(e)
+    }
+}
+
+impl From<PyErr> for StageError {
+    fn from(e: PyErr) -> Self {
+        StageError::Python(e)
+    }
+}
+
+impl StageError {
+    /// tags the error with the batch number it happened on
+    pub fn at(self, batch: usize) -> BatchError {
+        BatchError { batch, error: self }
+    }
+
+    /// says which file the error came from
+    pub fn in_file(self, path: &str) -> StageError {
+        StageError::File { path: path.to_string(), error: Box::new(self) }
+    }
+
+    /// for errors found while building the pipeline, before anything runs
+    /// io errors keep their python type (FileNotFoundError etc.), the rest become otters.SchemaError
+    pub fn into_schema_pyerr(self, stage: usize, kind: &str) -> PyErr {
+        match self {
+            StageError::Io(e) => e.into(),
+            StageError::File { path, error } if matches!(*error, StageError::Io(_)) => {
+                let StageError::Io(e) = *error else { unreachable!() };
+                std::io::Error::new(e.kind(), format!("{}: {}", path, e)).into()
+            }
+            other => SchemaError::new_err(format!("stage {} ({}): {}", stage, kind, other)),
+        }
+    }
+}
+
+/// a stage error plus which batch (0 based) the stage was on
+///
+/// every stage thread returns Result<(), BatchError>, the pipeline adds
+/// the stage index and kind when it turns this into a python exception
+#[derive(Debug)]
+pub struct BatchError {
+    pub batch: usize,
+    pub error: StageError,
+}
+
+/// what every stage thread returns when joined
+pub type StageResult = Result<(), BatchError>;
+
+impl BatchError {
+    /// builds otters.PipelineError with stage/kind/batch set as attributes
+    /// python errors get chained as __cause__ so the original traceback survives
+    pub fn into_pyerr(self, py: Python<'_>, stage: usize, kind: &str) -> PyErr {
+        let msg = format!("stage {} ({}) failed on batch {}: {}", stage, kind, self.batch, self.error);
+        let err = PipelineError::new_err(msg);
+        let value = err.value(py);
+        value.setattr("stage", stage).ok();
+        value.setattr("kind", kind).ok();
+        value.setattr("batch", self.batch).ok();
+        if let StageError::Python(cause) = self.error {
+            err.set_cause(py, Some(cause));
+        }
+        err
+    }
+}
+
+/// what a stage does when process() fails on a batch
+///
+/// fail        - stop the pipeline and raise from run() (default)
+/// skip        - drop the batch, count it, keep going
+/// dead_letter - send the batch + error to the pipeline's dead letter target, keep going
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    Fail,
+    Skip,
+    DeadLetter,
+}
+
+impl ErrorPolicy {
+    pub fn parse(s: &str) -> PyResult<Self> {
+        match s {
+            "fail" => Ok(ErrorPolicy::Fail),
+            "skip" => Ok(ErrorPolicy::Skip),
+            "dead_letter" => Ok(ErrorPolicy::DeadLetter),
+            other => Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "unknown on_error policy '{}', expected 'fail', 'skip' or 'dead_letter'", other
+            ))),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ErrorPolicy::Fail => "fail",
+            ErrorPolicy::Skip => "skip",
+            ErrorPolicy::DeadLetter => "dead_letter",
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use arrow::record_batch::RecordBatch;
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender};
+use pyo3::prelude::*;
+use pyo3::exceptions::PyValueError;
+use pyo3::types::PyDict;
+use crate::channel::{CancelToken, Progress};
+use crate::error::{PipelineError, StageResult};
+
+/// how often a blocking wait wakes up to let ctrl-c through
+const SIGNAL_CHECK: Duration = Duration::from_millis(100);
+
+/// every thread's result in pipeline order, the dead letter thread last
+type Outcome = Vec<(usize, &'static str, std::thread::Result<StageResult>)>;
+
+/// the stage threads of a started pipeline
+pub struct Running {
+    /// gets the outcome once every thread has finished
+    done: Receiver<Outcome>,
+    /// the last stage's batches when the pipeline doesn't end in a sink
+    pub output: Option<Receiver<RecordBatch>>,
+    pub cancel: CancelToken,
+    progress: Vec<(usize, &'static str, Arc<Progress>)>,
+}
+
+impl Running {
+    /// the threads get joined on one more thread, so waiting for them can time out
+    ///
+    /// `verdict` is a sink that only commits if the run went through (postgres). before its
+    /// thread is joined it gets sent whether every stage before it finished cleanly and the run
+    /// wasn't cancelled, its input closing alone doesn't say that
+    pub fn new(
+        handles: Vec<(usize, &'static str, JoinHandle<StageResult>)>,
+        output: Option<Receiver<RecordBatch>>,
+        cancel: CancelToken,
+        progress: Vec<(usize, &'static str, Arc<Progress>)>,
+        verdict: Option<(usize, Sender<bool>)>,
+    ) -> Self {
+        let (tx, done) = crossbeam_channel::bounded(1);
+        let cancelled = cancel.clone();
+        std::thread::spawn(move || {
+            let mut outcome: Outcome = Vec::with_capacity(handles.len());
+            for (idx, kind, handle) in handles {
+                if let Some((sink, upstream_ok)) = &verdict
+                    && *sink == idx
+                {
+                    let ok = !cancelled.is_cancelled()
+                        && outcome.iter().all(|(_, _, result)| matches!(result, Ok(Ok(()))));
+                    upstream_ok.send(ok).ok();
+                }
+                outcome.push((idx, kind, handle.join()));
+            }
+            tx.send(outcome).ok();
+        });
+        Self { done, output, cancel, progress }
+    }
+
+    /// no sink, what comes out the end is thrown away so the last stage never blocks on it
+    pub fn discard_output(&mut self) {
+        if let Some(output) = self.output.take() {
+            std::thread::spawn(move || output.iter().for_each(drop));
+        }
+    }
+
+    /// None if timeout passes first, ctrl-c comes out as KeyboardInterrupt
+    fn wait(&self, py: Python<'_>, timeout: Option<Duration>) -> PyResult<Option<Outcome>> {
+        let deadline = timeout.map(|t| Instant::now() + t);
+        loop {
+            let step = deadline.map_or(SIGNAL_CHECK, |d| d.saturating_duration_since(Instant::now()).min(SIGNAL_CHECK));
+            match py.detach(|| self.done.recv_timeout(step)) {
+                Ok(outcome) => return Ok(Some(outcome)),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => unreachable!("the joining thread always sends"),
+            }
+            py.check_signals()?;
+            if deadline.is_some_and(|d| Instant::now() >= d) {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// blocks until every stage is done and raises the first failure
+    ///
+    /// on ctrl-c the run is cancelled and waited for (so sinks close their files) before
+    /// KeyboardInterrupt is raised, a second ctrl-c stops waiting
+    pub fn finish(mut self, py: Python<'_>) -> PyResult<()> {
+        match self.wait(py, None) {
+            Ok(outcome) => report(py, outcome.unwrap()),
+            Err(interrupt) => {
+                self.cancel.cancel();
+                // nobody reads the output anymore, dropping it unblocks the last stage
+                self.output = None;
+                self.wait(py, None)?;
+                Err(interrupt)
+            }
+        }
+    }
+
+    /// next batch out of the last stage, None once the stages are done with it
+    ///
+    /// ctrl-c cancels the run, the caller should drop the output after so nothing blocks on it
+    pub fn next_batch(&self, py: Python<'_>, output: &Receiver<RecordBatch>) -> PyResult<Option<RecordBatch>> {
+        loop {
+            match py.detach(|| output.recv_timeout(SIGNAL_CHECK)) {
+                Ok(batch) => return Ok(Some(batch)),
+                Err(RecvTimeoutError::Disconnected) => return Ok(None),
+                Err(RecvTimeoutError::Timeout) => {}
+            }
+            if let Err(interrupt) = py.check_signals() {
+                self.cancel.cancel();
+                return Err(interrupt);
+            }
+        }
+    }
+}
+
+/// handles are in pipeline order so this raises the most upstream failure,
+/// stages that stopped because a neighbour hung up (or the run was cancelled) return Ok
+fn report(py: Python<'_>, outcome: Outcome) -> PyResult<()> {
+    for (idx, kind, result) in outcome {
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => return Err(e.into_pyerr(py, idx, kind)),
+            Err(_) => {
+                return Err(PipelineError::new_err(
+                    format!("stage {} ({}) panicked", idx, kind)
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// what Pipeline.start() returns, the pipeline keeps running in the background
+///
+/// dropping the handle doesn't stop it, cancel() does
+#[pyclass]
+pub struct PipelineHandle {
+    running: Running,
+    /// set once every stage is done, with the error if one failed
+    finished: Option<Option<PyErr>>,
+}
+
+impl PipelineHandle {
+    pub fn new(running: Running) -> Self {
+        Self { running, finished: None }
+    }
+
+    fn result(&self, py: Python<'_>) -> PyResult<()> {
+        match &self.finished {
+            Some(Some(e)) => Err(e.clone_ref(py)),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[pymethods]
+impl PipelineHandle {
+    /// blocks until the pipeline is done or timeout seconds pass, True if it's done.
+    /// a failed stage is raised as PipelineError, from every call after too.
+    /// ctrl-c raises KeyboardInterrupt here but leaves the pipeline running, cancel() stops it
+    #[pyo3(signature = (timeout=None))]
+    fn wait(&mut self, py: Python<'_>, timeout: Option<f64>) -> PyResult<bool> {
+        let timeout = timeout.map(Duration::try_from_secs_f64).transpose()
+            .map_err(|_| PyValueError::new_err("timeout must be a positive number of seconds"))?;
+        if self.finished.is_none() {
+            let Some(outcome) = self.running.wait(py, timeout)? else {
+                return Ok(false);
+            };
+            self.finished = Some(report(py, outcome).err());
+        }
+        self.result(py).map(|_| true)
+    }
+
+    /// stops the sources, the stages after them finish and the sinks close their files.
+    /// returns right away, wait() for it to be done
+    fn cancel(&self) {
+        self.running.cancel.cancel();
+    }
+
+    fn is_running(&mut self, py: Python<'_>) -> bool {
+        if self.finished.is_none()
+            && let Ok(outcome) = self.running.done.try_recv()
+        {
+            self.finished = Some(report(py, outcome).err());
+        }
+        self.finished.is_none()
+    }
+
+    /// batches and rows every stage has sent on so far, for sinks what reached them
+    /// [{'stage': 0, 'kind': 'parquet_source', 'batches': 40, 'rows': 100000}, ...]
+    fn progress<'py>(&self, py: Python<'py>) -> PyResult<Vec<Bound<'py, PyDict>>> {
+        self.running.progress.iter().map(|(stage, kind, counts)| {
+            let d = PyDict::new(py);
+            d.set_item("stage", stage)?;
+            d.set_item("kind", kind)?;
+            d.set_item("batches", counts.batches.load(Ordering::Relaxed))?;
+            d.set_item("rows", counts.rows.load(Ordering::Relaxed))?;
+            Ok(d)
+        }).collect()
+    }
+}
\ No newline at end of file
@@ -1,14 +1,27 @@
 use pyo3::prelude::*;
 
+mod error;
 mod compute;
 mod batcher;
+mod callback;
+mod channel;
 mod builtins;
 mod sources;
 mod sinks;
+mod postgres;
+mod lineage;
+mod handle;
 mod pipeline;
 
 #[pymodule]
 fn otters(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<pipeline::Pipeline>()?;
+    m.add_class::<pipeline::BatchIterator>()?;
+    m.add_class::<handle::PipelineHandle>()?;
+    m.add_class::<postgres::Postgres>()?;
+    m.add_function(wrap_pyfunction!(postgres::postgres, m)?)?;
+    m.add_function(wrap_pyfunction!(lineage::read_lineage, m)?)?;
+    m.add("PipelineError", m.py().get_type::<error::PipelineError>())?;
+    m.add("SchemaError", m.py().get_type::<error::SchemaError>())?;
     Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,107 @@
+use std::fs::File;
+use ::postgres::config::Host;
+use chrono::{DateTime, SecondsFormat, Utc};
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use pyo3::prelude::*;
+use pyo3::exceptions::PyValueError;
+use serde_json::{Value, json};
+
+/// footer key the lineage json is stored under
+pub const LINEAGE_KEY: &str = "otters.lineage";
+
+/// path, size and mtime of a file a source read, so a changed input can be spotted later
+pub fn file_info(path: &str) -> Value {
+    match std::fs::metadata(path) {
+        Ok(meta) => json!({
+            "path": path,
+            "bytes": meta.len(),
+            "modified": meta.modified().ok().map(|t| timestamp(t.into())),
+        }),
+        Err(_) => json!({ "path": path }),
+    }
+}
+
+pub fn timestamp(t: DateTime<Utc>) -> String {
+    t.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// where a postgres dsn points, as "host=.. port=.. dbname=.. user=.." whatever style it came in
+///
+/// the dsn is parsed and only those four are kept, so a password can't get through in any
+/// spelling (password = x, quoted, ?password= in a url) and neither can sslkey paths etc.
+pub fn redact_dsn(dsn: &str) -> String {
+    let Ok(config) = dsn.parse::<::postgres::Config>() else {
+        // nothing in it can be trusted to not be a password
+        return "(unparseable dsn)".to_string();
+    };
+    let hosts: Vec<String> = config.get_hosts().iter()
+        .map(|host| match host {
+            Host::Tcp(name) => name.clone(),
+            Host::Unix(dir) => dir.display().to_string(),
+        })
+        .collect();
+    let ports: Vec<String> = config.get_ports().iter().map(|p| p.to_string()).collect();
+
+    let mut parts = Vec::new();
+    if !hosts.is_empty() {
+        parts.push(format!("host={}", dsn_value(&hosts.join(","))));
+    }
+    if !ports.is_empty() {
+        parts.push(format!("port={}", ports.join(",")));
+    }
+    if let Some(dbname) = config.get_dbname() {
+        parts.push(format!("dbname={}", dsn_value(dbname)));
+    }
+    if let Some(user) = config.get_user() {
+        parts.push(format!("user={}", dsn_value(user)));
+    }
+    parts.join(" ")
+}
+
+/// quoted the way libpq wants when it has spaces or quotes in it
+fn dsn_value(value: &str) -> String {
+    if !value.is_empty() && !value.contains([' ', '\'', '\\']) {
+        return value.to_string();
+    }
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// the lineage a parquet sink wrote into a file's footer, as a dict
+///
+/// stages (with their parameters), the files / query the sources read, batch size,
+/// run start time and otters version. None for files otters didn't write
+#[pyfunction]
+pub fn read_lineage(py: Python<'_>, path: &str) -> PyResult<Option<Py<PyAny>>> {
+    let reader = SerializedFileReader::new(File::open(path)?)
+        .map_err(|e| PyValueError::new_err(format!("{}: {}", path, e)))?;
+    let lineage = reader.metadata().file_metadata().key_value_metadata()
+        .and_then(|kv| kv.iter().find(|kv| kv.key == LINEAGE_KEY))
+        .and_then(|kv| kv.value.clone());
+    let Some(lineage) = lineage else {
+        return Ok(None);
+    };
+    let parsed = py.import("json")?.call_method1("loads", (lineage,))?;
+    Ok(Some(parsed.unbind()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redact_dsn_drops_the_password() {
+        let cases = [
+            ("host=db user=me password=secret dbname=ticks", "host=db dbname=ticks user=me"),
+            ("host=db password = secret user=me", "host=db user=me"),
+            ("host=db password='s3 cret' user=me", "host=db user=me"),
+            ("postgres://me:secret@db:5433/ticks", "host=db port=5433 dbname=ticks user=me"),
+            ("postgres://db/ticks?password=secret&user=me", "host=db port=5432 dbname=ticks user=me"),
+            ("postgresql://me@db/ticks?sslmode=disable", "host=db port=5432 dbname=ticks user=me"),
+            ("host=/tmp/pg port=55432 user='a b'", "host=/tmp/pg port=55432 user='a b'"),
+        ];
+        for (dsn, expected) in cases {
+            assert_eq!(redact_dsn(dsn), expected, "{}", dsn);
+        }
+        assert!(!redact_dsn("host=db password='secret").contains("secret"));
+    }
+}
@@ -1,16 +1,46 @@
+use std::collections::HashSet;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread::JoinHandle;
+use std::time::Duration;
 use crossbeam_channel::{Receiver, Sender};
 use pyo3::prelude::*;
+use pyo3::exceptions::PyStopIteration;
+use pyo3::types::PyDict;
+use arrow::datatypes::{Field, Schema, SchemaRef};
 use arrow::record_batch::RecordBatch;
-use arrow::pyarrow::{FromPyArrow, ToPyArrow};
+use serde_json::json;
+use parquet::basic::{BrotliLevel, Compression, GzipLevel, ZstdLevel};
+use arrow::pyarrow::{FromPyArrow, IntoPyArrow, Table, ToPyArrow};
 use crate::compute::ComputeStage;
-use crate::batcher::spawn_batcher;
+use crate::error::{ErrorPolicy, PipelineError, SchemaError, StageError, StageResult};
+use crate::batcher::{check_schema as check_row_schema, spawn_batcher};
+use crate::builtins::nulls::NullPolicy;
 use crate::builtins::rolling_mean::RollingMean;
-use crate::builtins::zscore::ZScore;
+use crate::builtins::zscore::{ZScore, parse_output_type};
 use crate::builtins::ema::Ema;
 use crate::builtins::vwap::Vwap;
-use crate::sources::parquet_reader::spawn_parquet_source;
-use crate::sinks::parquet_writer::spawn_parquet_sink;
+use crate::builtins::select::Select;
+use crate::sources::parquet_reader::{ParquetScan, ScanFile, resolve_paths, spawn_parquet_source};
+use crate::sources::filter::{Predicate, parse_filter};
+use crate::sources::hive::discover;
+use crate::sources::csv_reader::{CsvReadOptions, csv_schema, is_csv_path, spawn_csv_source};
+use crate::sources::postgres_reader::{postgres_schema, spawn_postgres_source};
+use crate::sources::ipc_reader::{IpcFormat, ipc_schema, is_stdio, spawn_ipc_source};
+use crate::sources::arrow_stream::{ArrowStream, spawn_arrow_stream_source};
+use crate::sinks::parquet_writer::{ParquetWriteOptions, spawn_parquet_sink};
+use crate::sinks::partitioned::{data_schema, spawn_partitioned_parquet_sink};
+use crate::sinks::rotating::{Rotation, Template, spawn_rotating_parquet_sink};
+use crate::sinks::csv_writer::{CsvWriteOptions, spawn_csv_sink};
+use crate::sinks::postgres_writer::{check_schema, spawn_postgres_sink};
+use crate::sinks::ipc_writer::spawn_ipc_sink;
+use crate::postgres::Postgres;
+use crate::lineage;
+use crate::channel::{CancelToken, Output, Progress};
+use crate::handle::{PipelineHandle, Running};
+use crate::callback::{CallbackMode, call_transform, check_output};
+use crate::sinks::callback::spawn_callback_sink;
+use crate::sinks::dead_letter::{DeadLetter, spawn_dead_letter_callback, spawn_dead_letter_parquet};
 
 /// what role a stage plays in the pipeline
 /// 
@@ -20,20 +50,207 @@ use crate::sinks::parquet_writer::spawn_parquet_sink;
 /// 
 /// stage  - receives items and transforms via python callback, sends results
 enum StageKind {
-    Source(Py<PyAny>),
-    ParquetSource(String),
-    Sink(Py<PyAny>),
-    ParquetSink(String),
+    /// python generator, with schema= its rows go straight into arrow (see batcher)
+    Source(Py<PyAny>, Option<SchemaRef>),
+    /// one or more files, footers are read when the source is registered
+    ParquetSource(ParquetScan),
+    /// schema is inferred (or given) when the source is registered
+    CsvSource { path: String, schema: SchemaRef, options: CsvReadOptions },
+    /// schema comes from preparing the query when the source is registered
+    PostgresSource { dsn: String, query: String, schema: SchemaRef },
+    IpcSource(String, IpcFormat),
+    /// pyarrow / polars / pandas / duckdb object, read through the arrow C stream interface
+    ArrowSource(ArrowStream),
+    Sink(Py<PyAny>, CallbackMode),
+    ParquetSink(String, ParquetWriteOptions),
+    /// sink(rotate_...=), output split over files named from the path template
+    RotatingParquetSink { template: Template, options: ParquetWriteOptions, rotation: Rotation },
+    /// sink(dir, partition_by=[...]), hive style directories with one writer per partition
+    PartitionedParquetSink { root: String, partition_by: Vec<String>, max_open: usize, options: ParquetWriteOptions },
+    CsvSink(String, CsvWriteOptions),
+    PostgresSink { dsn: String, table: String },
+    IpcSink(String, IpcFormat),
     Stage(Box<dyn ComputeStage + Send + Sync>),
-    PyTransform(Py<PyAny>),
+    /// schema is the declared output schema, None means whatever the callback returns first
+    PyTransform { callback: Py<PyAny>, mode: CallbackMode, schema: Option<SchemaRef> },
+}
+
+impl StageKind {
+    /// name used when reporting which stage failed
+    fn name(&self) -> &'static str {
+        match self {
+            StageKind::Source(..) => "source",
+            StageKind::ParquetSource(_) => "parquet_source",
+            StageKind::CsvSource { .. } => "csv_source",
+            StageKind::PostgresSource { .. } => "postgres_source",
+            StageKind::IpcSource(..) => "ipc_source",
+            StageKind::ArrowSource(_) => "arrow_source",
+            StageKind::Sink(..) => "sink",
+            StageKind::ParquetSink(..) | StageKind::RotatingParquetSink { .. } => "parquet_sink",
+            StageKind::PartitionedParquetSink { .. } => "partitioned_parquet_sink",
+            StageKind::CsvSink(..) => "csv_sink",
+            StageKind::PostgresSink { .. } => "postgres_sink",
+            StageKind::IpcSink(..) => "ipc_sink",
+            StageKind::Stage(compute) => compute.name(),
+            StageKind::PyTransform { .. } => "py_transform",
+        }
+    }
+
+    /// kind plus whatever is needed to tell later what the stage did (files read, parameters)
+    fn lineage(&self, py: Python<'_>) -> serde_json::Value {
+        let mut described = match self {
+            StageKind::Source(f, _) => json!({ "callable": describe_callable(py, f) }),
+            StageKind::PyTransform { callback, mode, .. } => {
+                json!({ "callable": describe_callable(py, callback), "mode": mode.name() })
+            }
+            StageKind::Sink(f, mode) => json!({ "callable": describe_callable(py, f), "mode": mode.name() }),
+            StageKind::ParquetSource(scan) => json!({
+                "files": scan.files.iter().map(|f| lineage::file_info(&f.path)).collect::<Vec<_>>(),
+                "partitioning": scan.partitions.iter().map(|f| f.name()).collect::<Vec<_>>(),
+                "columns": scan.columns,
+                "filter": scan.filter.iter().map(|t| t.describe()).collect::<Vec<_>>(),
+                "source_file": scan.source_file,
+            }),
+            StageKind::CsvSource { path, options, .. } => json!({
+                "files": [lineage::file_info(path)],
+                "delimiter": (options.delimiter as char).to_string(),
+                "has_header": options.has_header,
+                "timestamp_format": options.timestamp_format,
+            }),
+            StageKind::PostgresSource { dsn, query, .. } => json!({
+                "dsn": lineage::redact_dsn(dsn),
+                "query": query,
+            }),
+            StageKind::IpcSource(path, _) => json!({ "files": [lineage::file_info(path)] }),
+            StageKind::ArrowSource(stream) => json!({ "object": stream.type_name }),
+            StageKind::ParquetSink(path, _) => json!({ "path": path }),
+            StageKind::RotatingParquetSink { template, rotation, .. } => json!({
+                "path": template.to_string(),
+                "rotate_rows": rotation.rows,
+                "rotate_bytes": rotation.bytes,
+                "rotate_interval": rotation.interval.map(|d| d.as_secs_f64()),
+                "rotate_by": rotation.by,
+            }),
+            StageKind::PartitionedParquetSink { root, partition_by, .. } => json!({
+                "path": root,
+                "partition_by": partition_by,
+            }),
+            StageKind::CsvSink(path, _) | StageKind::IpcSink(path, _) => json!({ "path": path }),
+            StageKind::PostgresSink { dsn, table } => json!({
+                "dsn": lineage::redact_dsn(dsn),
+                "table": table,
+            }),
+            StageKind::Stage(compute) => json!({ "params": compute.params() }),
+        };
+        described["kind"] = json!(self.name());
+        described
+    }
+
+    /// stages that take batches and send nothing on
+    fn is_sink(&self) -> bool {
+        matches!(
+            self,
+            StageKind::Sink(..)
+                | StageKind::ParquetSink(..)
+                | StageKind::RotatingParquetSink { .. }
+                | StageKind::PartitionedParquetSink { .. }
+                | StageKind::CsvSink(..)
+                | StageKind::PostgresSink { .. }
+                | StageKind::IpcSink(..)
+        )
+    }
+
+    fn is_source(&self) -> bool {
+        matches!(self, StageKind::Source(..)) || self.is_batch_source()
+    }
+
+    /// file sources build RecordBatches themselves and skip the dict channel + batcher
+    fn is_batch_source(&self) -> bool {
+        matches!(
+            self,
+            StageKind::ParquetSource(_)
+                | StageKind::CsvSource { .. }
+                | StageKind::PostgresSource { .. }
+                | StageKind::IpcSource(..)
+                | StageKind::ArrowSource(_)
+        )
+    }
 }
 
 /// internal config for a stage
 /// 
-/// just a thin wrapper around stagekind right now
-/// TODO: add error handling policy and stage name for logging, etc.
+/// stagekind plus what to do when the stage fails on a batch
+/// on_error only matters for stages that process batches (builtins, py_transform),
+/// sources and sinks always fail the pipeline
 struct StageConfig {
     kind: StageKind,
+    on_error: ErrorPolicy,
+}
+
+impl StageConfig {
+    fn new(kind: StageKind) -> Self {
+        Self { kind, on_error: ErrorPolicy::Fail }
+    }
+
+    fn with_policy(kind: StageKind, on_error: &str) -> PyResult<Self> {
+        Ok(Self { kind, on_error: ErrorPolicy::parse(on_error)? })
+    }
+}
+
+/// where batches from on_error="dead_letter" stages end up
+enum DeadLetterTarget {
+    Parquet(String),
+    Callback(Py<PyAny>),
+}
+
+/// skip / dead letter counters for one stage, shared with its worker thread
+struct ErrorCounts {
+    stage: usize,
+    kind: &'static str,
+    skipped: AtomicUsize,
+    dead_lettered: AtomicUsize,
+}
+
+/// thread side of a stage's on_error policy
+struct ErrorHandler {
+    policy: ErrorPolicy,
+    counts: Arc<ErrorCounts>,
+    dead_letters: Option<Sender<DeadLetter>>,
+}
+
+impl ErrorHandler {
+    /// only dead lettering needs the input batch kept around after process()
+    fn keeps_input(&self) -> bool {
+        self.policy == ErrorPolicy::DeadLetter
+    }
+
+    /// applies the policy to a failed batch, Err means the stage should stop
+    fn handle(&self, n: usize, input: Option<RecordBatch>, err: StageError) -> StageResult {
+        match self.policy {
+            ErrorPolicy::Fail => Err(err.at(n)),
+            ErrorPolicy::Skip => {
+                self.counts.skipped.fetch_add(1, Ordering::Relaxed);
+                Ok(())
+            }
+            ErrorPolicy::DeadLetter => {
+                let (Some(tx), Some(batch)) = (&self.dead_letters, input) else {
+                    return Err(err.at(n));
+                };
+                let letter = DeadLetter {
+                    stage: self.counts.stage,
+                    kind: self.counts.kind,
+                    error: err.to_string(),
+                    batch,
+                };
+                // if the dead letter thread died its own error gets raised from run(),
+                // the batch is lost so it isn't counted
+                if tx.send(letter).is_ok() {
+                    self.counts.dead_lettered.fetch_add(1, Ordering::Relaxed);
+                }
+                Ok(())
+            }
+        }
+    }
 }
 
 /// multi stage pipeline
@@ -52,93 +269,596 @@ pub struct Pipeline {
     stages: Vec<StageConfig>,
     capacity: usize,
     batch_size: usize,
+    /// a python source's partial batch goes out once its oldest row has waited this long
+    flush_interval: Option<Duration>,
+    dead_letter: Option<DeadLetterTarget>,
+    /// schema coming out of the last registered stage
+    /// None when it can't be known before run() (python generator source, after py_transform)
+    schema: Option<SchemaRef>,
+    /// filled in by run() for every stage with on_error != "fail"
+    error_counts: Vec<Arc<ErrorCounts>>,
 }
 
 #[pymethods]
 impl Pipeline {
+    /// flush_ms is the latency budget for python generator sources, a slow feed gets sent on
+    /// in partial batches instead of waiting for batch_size rows. None waits for full batches
     #[new]
-    #[pyo3(signature = (capacity=1024, batch_size=2500))]
-    pub fn new(capacity: usize, batch_size: usize) -> Pipeline {
-        Pipeline { stages: vec![], capacity, batch_size }
+    #[pyo3(signature = (capacity=1024, batch_size=2500, flush_ms=None))]
+    pub fn new(capacity: usize, batch_size: usize, flush_ms: Option<u64>) -> PyResult<Pipeline> {
+        // the sources slice batch_size rows at a time, 0 would never get anywhere
+        if batch_size == 0 {
+            return Err(pyo3::exceptions::PyValueError::new_err("batch_size must be >= 1"));
+        }
+        if flush_ms == Some(0) {
+            return Err(pyo3::exceptions::PyValueError::new_err("flush_ms must be >= 1"));
+        }
+        Ok(Pipeline {
+            stages: vec![],
+            capacity,
+            batch_size,
+            flush_interval: flush_ms.map(Duration::from_millis),
+            dead_letter: None,
+            schema: None,
+            error_counts: vec![],
+        })
     }
 
-    fn source(&mut self, src: Py<PyAny>, py: Python<'_>) {
+    /// parquet sources read the footer right away so later stages can be validated
+    ///
+    /// a glob ("ticks/2025-*.parquet"), a directory or a list of paths streams the files one
+    /// after another (globs/dirs sorted, lists in the order given) so rolling state carries
+    /// across them, source_file=True adds a column with the file each row came from
+    ///
+    /// filter=[("ts", ">=", "2025-01-02T09:30"), ("symbol", "in", [...])] skips parquet row groups
+    /// and pages whose stats / bloom filters rule them out, and drops non matching rows
+    ///
+    /// partitioning="hive" reads a key=value directory layout, the keys become columns and
+    /// filter terms on them skip whole directories
+    ///
+    /// columns=[...] only reads those columns from parquet, run() also does this on its own
+    /// when a select() makes it clear the rest of the file isn't needed
+    ///
+    /// .csv / .csv.gz / .csv.zst paths infer their schema from the first infer_rows rows,
+    /// or take schema= (otters.Schema or pyarrow.Schema) as is
+    ///
+    /// otters.postgres(dsn, query) prepares the query right away for its schema
+    ///
+    /// .arrow / .feather / .ipc are arrow ipc files, .arrows an ipc stream, "-" a stream on stdin
+    ///
+    /// anything with __arrow_c_stream__ / __arrow_c_array__ (pyarrow Table, RecordBatch,
+    /// RecordBatchReader, polars / pandas DataFrame, duckdb relation) is streamed as arrow
+    /// from rust in batch_size batches
+    ///
+    /// anything else is called for an iterator of rows. with schema= the rows (dicts, tuples or
+    /// dataclasses) are built into arrow columns in rust and the schema is known up front,
+    /// without it pyarrow infers the types of every batch
+    #[pyo3(signature = (
+        src, schema=None, delimiter=",", has_header=true, timestamp_format=None, infer_rows=1000,
+        source_file=false, partitioning=None, filter=None, columns=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn source(
+        &mut self,
+        src: Py<PyAny>,
+        schema: Option<Bound<'_, PyAny>>,
+        delimiter: &str,
+        has_header: bool,
+        timestamp_format: Option<String>,
+        infer_rows: usize,
+        source_file: bool,
+        partitioning: Option<&str>,
+        filter: Option<Bound<'_, PyAny>>,
+        columns: Option<Vec<String>>,
+        py: Python<'_>,
+    ) -> PyResult<()> {
+        let filter = filter.map(|f| parse_filter(&f)).transpose()?.unwrap_or_default();
+        if let Some(partitioning) = partitioning {
+            if partitioning != "hive" {
+                return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                    "unknown partitioning '{}', only 'hive' is supported", partitioning
+                )));
+            }
+            let root = src.extract::<String>(py)?;
+            let (files, partitions) = discover(&root, &filter)
+                .map_err(|e| e.into_schema_pyerr(self.stages.len(), "parquet_source"))?;
+            return self.parquet_source(files, partitions, source_file, columns, &filter);
+        }
+
+        if let Ok(files) = src.extract::<Vec<String>>(py) {
+            let files = files.into_iter().map(ScanFile::from).collect();
+            return self.parquet_source(files, vec![], source_file, columns, &filter);
+        }
+        if let Ok(s) = src.extract::<String>(py)
+            && (s.ends_with(".parquet") || std::path::Path::new(&s).is_dir())
+        {
+            let files = resolve_paths(&s)
+                .map_err(|e| e.into_schema_pyerr(self.stages.len(), "parquet_source"))?;
+            let files = files.into_iter().map(ScanFile::from).collect();
+            return self.parquet_source(files, vec![], source_file, columns, &filter);
+        }
+        if columns.is_some() || !filter.is_empty() {
+            return Err(pyo3::exceptions::PyValueError::new_err("columns= and filter= only work for parquet sources"));
+        }
         if let Ok(s) = src.extract::<String>(py) {
-            if s.ends_with(".parquet") {
-                self.stages.push(StageConfig {
-                    kind: StageKind::ParquetSource(s),
-                });
-                return;
+            if is_csv_path(&s) {
+                let mut options = CsvReadOptions {
+                    delimiter: delimiter_byte(delimiter)?,
+                    has_header,
+                    timestamp_format,
+                    infer_rows,
+                    timestamp_columns: vec![],
+                };
+                let explicit = schema.map(|s| arrow_schema(&s)).transpose()?;
+                let (schema, timestamp_columns) = csv_schema(&s, &options, explicit)
+                    .map_err(|e| e.into_schema_pyerr(self.stages.len(), "csv_source"))?;
+                options.timestamp_columns = timestamp_columns;
+                return self.push_stage(StageConfig::new(StageKind::CsvSource { path: s, schema, options }));
+            }
+            if let Some(format) = IpcFormat::from_path(&s) {
+                return self.push_stage(StageConfig::new(StageKind::IpcSource(s, format)));
             }
         }
+        if let Ok(pg) = src.bind(py).cast::<Postgres>() {
+            let pg = pg.get();
+            let Some(query) = pg.query.clone() else {
+                return Err(pyo3::exceptions::PyValueError::new_err(
+                    "postgres source needs a query, postgres(dsn, \"select ...\")"
+                ));
+            };
+            let schema = postgres_schema(&pg.dsn, &query)
+                .map_err(|e| e.into_schema_pyerr(self.stages.len(), "postgres_source"))?;
+            return self.push_stage(StageConfig::new(
+                StageKind::PostgresSource { dsn: pg.dsn.clone(), query, schema }
+            ));
+        }
+
+        if let Some(stream) = ArrowStream::from_py(src.bind(py))? {
+            return self.push_stage(StageConfig::new(StageKind::ArrowSource(stream)));
+        }
 
         // fallback: python generator
-        self.stages.push(StageConfig {
-            kind: StageKind::Source(src),
-        });
+        let schema = schema.map(|s| arrow_schema(&s)).transpose()?;
+        if let Some(schema) = &schema {
+            check_row_schema(schema).map_err(|e| e.into_schema_pyerr(self.stages.len(), "source"))?;
+        }
+        self.push_stage(StageConfig::new(StageKind::Source(src, schema)))
     }
 
-    fn sink(&mut self, target: Py<PyAny>, py: Python<'_>) {
+    /// .parquet or .csv / .csv.gz / .csv.zst paths are written from rust,
+    /// the csv kwargs are ignored for anything else
+    ///
+    /// parquet kwargs: compression="snappy" | "gzip" | "brotli" | "lz4" | "zstd" | "none"
+    /// (compression_level for gzip/brotli/zstd), row_group_size in rows, data_page_size in bytes,
+    /// dictionary / statistics on or off, bloom_filter_columns=[...], and sorting_columns=[...]
+    /// which only records the (ascending) order the data is already in. unset ones keep the
+    /// parquet defaults
+    ///
+    /// rotate_rows / rotate_bytes / rotate_interval (seconds) / rotate_by="date" split parquet
+    /// output over several files, each closed with a footer as it rotates. the path is a
+    /// template with {n} (file number), {value} (rotate_by value) and {time} (utc open time),
+    /// e.g. "out/signals-{value}-{n}.parquet"
+    ///
+    /// partition_by="date" or ["symbol", "date"] writes a hive style dataset under the target
+    /// directory (out/symbol=AAPL/date=2025-01-02/part-00000.parquet), the partition columns
+    /// go in the directory names instead of the files. max_open_files caps how many files are
+    /// open at once, least recently used ones get closed first
+    ///
+    /// otters.postgres(dsn, table="...") COPYs into the table, creating it if needed
+    ///
+    /// arrow ipc paths work like source(), "-" streams to stdout
+    ///
+    /// anything else is a python callback, mode="row" (default) calls it with a dict per row,
+    /// "batch" with a pyarrow.RecordBatch per batch, "numpy" with a {column: ndarray} dict
+    /// and "polars" with a polars.DataFrame
+    #[pyo3(signature = (
+        target, delimiter=",", has_header=true, timestamp_format=None,
+        compression=None, compression_level=None, row_group_size=None, dictionary=None,
+        statistics=None, bloom_filter_columns=None, sorting_columns=None, data_page_size=None,
+        rotate_rows=None, rotate_bytes=None, rotate_interval=None, rotate_by=None,
+        partition_by=None, max_open_files=128, mode="row",
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn sink(
+        &mut self,
+        target: Py<PyAny>,
+        delimiter: &str,
+        has_header: bool,
+        timestamp_format: Option<String>,
+        compression: Option<&str>,
+        compression_level: Option<i32>,
+        row_group_size: Option<usize>,
+        dictionary: Option<bool>,
+        statistics: Option<bool>,
+        bloom_filter_columns: Option<Vec<String>>,
+        sorting_columns: Option<Vec<String>>,
+        data_page_size: Option<usize>,
+        rotate_rows: Option<usize>,
+        rotate_bytes: Option<usize>,
+        rotate_interval: Option<f64>,
+        rotate_by: Option<String>,
+        partition_by: Option<Bound<'_, PyAny>>,
+        max_open_files: usize,
+        mode: &str,
+        py: Python<'_>,
+    ) -> PyResult<()> {
+        let mode = CallbackMode::parse(mode)?;
+        if mode != CallbackMode::Row && !target.bind(py).is_callable() {
+            return Err(pyo3::exceptions::PyValueError::new_err("mode= only works for python callback sinks"));
+        }
+        if row_group_size == Some(0) || data_page_size == Some(0) || rotate_rows == Some(0) || rotate_bytes == Some(0) {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "row_group_size, data_page_size, rotate_rows and rotate_bytes must be > 0"
+            ));
+        }
+        let interval = rotate_interval
+            .map(|secs| {
+                Duration::try_from_secs_f64(secs).ok().filter(|d| !d.is_zero()).ok_or_else(|| {
+                    pyo3::exceptions::PyValueError::new_err("rotate_interval must be a positive number of seconds")
+                })
+            })
+            .transpose()?;
+        let rotation = Rotation { rows: rotate_rows, bytes: rotate_bytes, interval, by: rotate_by };
+        let rotating = rotation.rows.is_some() || rotation.bytes.is_some() || rotation.interval.is_some()
+            || rotation.by.is_some();
+        let options = ParquetWriteOptions {
+            compression: parquet_compression(compression, compression_level)?,
+            row_group_size,
+            dictionary,
+            statistics,
+            bloom_filter_columns: bloom_filter_columns.unwrap_or_default(),
+            sorting_columns: sorting_columns.unwrap_or_default(),
+            data_page_size,
+            lineage: None,
+        };
+        if let Some(columns) = partition_by {
+            // one column can be passed as a plain string
+            let partition_by = match columns.extract::<String>() {
+                Ok(column) => vec![column],
+                Err(_) => columns.extract::<Vec<String>>()?,
+            };
+            let Ok(root) = target.extract::<String>(py) else {
+                return Err(pyo3::exceptions::PyValueError::new_err("partition_by needs a directory path to write to"));
+            };
+            if partition_by.is_empty() || max_open_files == 0 {
+                return Err(pyo3::exceptions::PyValueError::new_err(
+                    "partition_by needs at least one column and max_open_files must be > 0"
+                ));
+            }
+            if rotating {
+                return Err(pyo3::exceptions::PyValueError::new_err("rotate_...= doesn't work with partition_by"));
+            }
+            return self.push_stage(StageConfig::new(StageKind::PartitionedParquetSink {
+                root, partition_by, max_open: max_open_files, options,
+            }));
+        }
+        if let Ok(s) = target.extract::<String>(py)
+            && s.ends_with(".parquet")
+        {
+            if rotating {
+                let template = Template::parse(&s, &rotation)?;
+                return self.push_stage(StageConfig::new(StageKind::RotatingParquetSink { template, options, rotation }));
+            }
+            return self.push_stage(StageConfig::new(StageKind::ParquetSink(s, options)));
+        }
+        if options != ParquetWriteOptions::default() || rotating {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "compression=, row_group_size=, rotate_...= etc only work for .parquet sinks"
+            ));
+        }
         if let Ok(s) = target.extract::<String>(py) {
-            if s.ends_with(".parquet") {
-                self.stages.push(StageConfig {
-                    kind: StageKind::ParquetSink(s),
-                });
-                return;
+            if is_csv_path(&s) {
+                let options = CsvWriteOptions {
+                    delimiter: delimiter_byte(delimiter)?,
+                    has_header,
+                    timestamp_format,
+                };
+                return self.push_stage(StageConfig::new(StageKind::CsvSink(s, options)));
             }
+            if let Some(format) = IpcFormat::from_path(&s) {
+                return self.push_stage(StageConfig::new(StageKind::IpcSink(s, format)));
+            }
+        }
+        if let Ok(pg) = target.bind(py).cast::<Postgres>() {
+            let pg = pg.get();
+            let Some(table) = pg.table.clone() else {
+                return Err(pyo3::exceptions::PyValueError::new_err(
+                    "postgres sink needs a table, postgres(dsn, table=\"...\")"
+                ));
+            };
+            return self.push_stage(StageConfig::new(StageKind::PostgresSink { dsn: pg.dsn.clone(), table }));
         }
 
         // fallback: python callable
-        self.stages.push(StageConfig {
-            kind: StageKind::Sink(target),
-        });
+        self.push_stage(StageConfig::new(StageKind::Sink(target, mode)))
+    }
+
+    /// the pyarrow schema the pipeline will emit, worked out from the source
+    /// schema and each stage's declared outputs without running anything
+    fn output_schema<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        match &self.schema {
+            Some(schema) => schema.to_pyarrow(py),
+            None => Err(SchemaError::new_err(
+                "output schema is unknown before run(), needs a file or postgres source (not stdin) and no py_transform stages without schema="
+            )),
+        }
+    }
+
+    /// where on_error="dead_letter" stages send the batches they failed on
+    ///
+    /// a .parquet path or a callable that gets a pyarrow RecordBatch,
+    /// either way the batch has _stage_index, _stage and _error columns appended
+    fn dead_letter(&mut self, target: Py<PyAny>, py: Python<'_>) {
+        if let Ok(s) = target.extract::<String>(py)
+            && s.ends_with(".parquet")
+        {
+            self.dead_letter = Some(DeadLetterTarget::Parquet(s));
+            return;
+        }
+        self.dead_letter = Some(DeadLetterTarget::Callback(target));
+    }
+
+    /// skip / dead letter counts from the last run(), one dict per stage
+    /// that doesn't use on_error="fail"
+    fn error_stats<'py>(&self, py: Python<'py>) -> PyResult<Vec<Bound<'py, PyDict>>> {
+        self.error_counts.iter().map(|c| {
+            let d = PyDict::new(py);
+            d.set_item("stage", c.stage)?;
+            d.set_item("kind", c.kind)?;
+            d.set_item("skipped", c.skipped.load(Ordering::Relaxed))?;
+            d.set_item("dead_lettered", c.dead_lettered.load(Ordering::Relaxed))?;
+            Ok(d)
+        }).collect()
     }
 
     ////stages
+    /// every stage takes on_error="fail" | "skip" | "dead_letter"
+    /// and partition_by="symbol" to keep separate rolling state per key value
+    /// and null_policy="skip" | "propagate" | "ffill" for null inputs
+    /// inputs can be any numeric column, output_dtype="float32" narrows the output
 
-    fn rolling_mean(&mut self, column: String, window: usize) {
-        self.stages.push(StageConfig {
-            kind: StageKind::Stage(Box::new(RollingMean::new(column, window))),
-        });
+    #[pyo3(signature = (column, window, partition_by=None, null_policy="skip", output_dtype="float64", on_error="fail"))]
+    fn rolling_mean(
+        &mut self,
+        column: String,
+        window: usize,
+        partition_by: Option<String>,
+        null_policy: &str,
+        output_dtype: &str,
+        on_error: &str,
+    ) -> PyResult<()> {
+        check_window("window", window, 1)?;
+        let nulls = NullPolicy::parse(null_policy)?;
+        let output_type = parse_output_type(output_dtype)?;
+        self.push_stage(StageConfig::with_policy(
+            StageKind::Stage(Box::new(RollingMean::new(column, window, partition_by, nulls, output_type))), on_error,
+        )?)
     }
 
-    fn zscore(&mut self, column: String, lookback: usize) {
-        self.stages.push(StageConfig {
-            kind: StageKind::Stage(Box::new(ZScore::new(column, lookback))),
-        });
+    #[pyo3(signature = (column, lookback, partition_by=None, null_policy="skip", output_dtype="float64", on_error="fail"))]
+    fn zscore(
+        &mut self,
+        column: String,
+        lookback: usize,
+        partition_by: Option<String>,
+        null_policy: &str,
+        output_dtype: &str,
+        on_error: &str,
+    ) -> PyResult<()> {
+        check_window("lookback", lookback, 2)?;
+        let nulls = NullPolicy::parse(null_policy)?;
+        let output_type = parse_output_type(output_dtype)?;
+        self.push_stage(StageConfig::with_policy(
+            StageKind::Stage(Box::new(ZScore::new(column, lookback, partition_by, nulls, output_type))), on_error,
+        )?)
     }
 
-    fn ema(&mut self, column: String, span: usize) {
-        self.stages.push(StageConfig {
-            kind: StageKind::Stage(Box::new(Ema::new(column, span))),
-        });
+    #[pyo3(signature = (column, span, partition_by=None, null_policy="skip", output_dtype="float64", on_error="fail"))]
+    fn ema(
+        &mut self,
+        column: String,
+        span: usize,
+        partition_by: Option<String>,
+        null_policy: &str,
+        output_dtype: &str,
+        on_error: &str,
+    ) -> PyResult<()> {
+        check_window("span", span, 1)?;
+        let nulls = NullPolicy::parse(null_policy)?;
+        let output_type = parse_output_type(output_dtype)?;
+        self.push_stage(StageConfig::with_policy(
+            StageKind::Stage(Box::new(Ema::new(column, span, partition_by, nulls, output_type))), on_error,
+        )?)
     }
 
-    fn vwap(&mut self, price_col: String, volume_col: String, window: usize) {
-        self.stages.push(StageConfig {
-            kind: StageKind::Stage(Box::new(Vwap::new(price_col, volume_col, window))),
-        });
+    #[pyo3(signature = (price_col, volume_col, window, partition_by=None, null_policy="skip", output_dtype="float64", on_error="fail"))]
+    #[allow(clippy::too_many_arguments)]
+    fn vwap(
+        &mut self,
+        price_col: String,
+        volume_col: String,
+        window: usize,
+        partition_by: Option<String>,
+        null_policy: &str,
+        output_dtype: &str,
+        on_error: &str,
+    ) -> PyResult<()> {
+        check_window("window", window, 1)?;
+        let nulls = NullPolicy::parse(null_policy)?;
+        let output_type = parse_output_type(output_dtype)?;
+        self.push_stage(StageConfig::with_policy(
+            StageKind::Stage(Box::new(Vwap::new(price_col, volume_col, window, partition_by, nulls, output_type))), on_error,
+        )?)
+    }
+
+    /// keeps only these columns (in this order), put it before a sink to trim what gets written.
+    /// with a parquet source, columns nothing downstream of the source uses are never read
+    #[pyo3(signature = (columns, on_error="fail"))]
+    fn select(&mut self, columns: Vec<String>, on_error: &str) -> PyResult<()> {
+        let unique: HashSet<&String> = columns.iter().collect();
+        if unique.len() != columns.len() {
+            return Err(pyo3::exceptions::PyValueError::new_err("select() got the same column twice"));
+        }
+        self.push_stage(StageConfig::with_policy(StageKind::Stage(Box::new(Select::new(columns))), on_error)?)
     }
 
-    fn py_transform(&mut self, callback: Py<PyAny>) {
-        self.stages.push(StageConfig { kind: StageKind::PyTransform(callback) });
+    /// mode="row" calls it once per row dict, returning None drops the row.
+    /// "batch", "numpy" and "polars" call it once per batch (see sink()), it returns a
+    /// pyarrow.RecordBatch, a {column: array} dict, a table / dataframe or None to drop the batch.
+    /// every batch it returns must match schema= (otters.Schema or pyarrow.Schema), without one
+    /// the first batch sets it. giving schema= also lets stages after it check at registration
+    #[pyo3(signature = (callback, on_error="fail", mode="row", schema=None))]
+    fn py_transform(
+        &mut self,
+        callback: Py<PyAny>,
+        on_error: &str,
+        mode: &str,
+        schema: Option<Bound<'_, PyAny>>,
+    ) -> PyResult<()> {
+        let mode = CallbackMode::parse(mode)?;
+        let schema = schema.map(|s| arrow_schema(&s)).transpose()?;
+        self.push_stage(StageConfig::with_policy(StageKind::PyTransform { callback, mode, schema }, on_error)?)
     }
 
     /// wires up channels between stages, spawns workers threads, and
-    /// blocks until the pipeline finishes
+    /// blocks until the pipeline finishes. ctrl-c cancels it, see PipelineHandle.cancel()
     /// 
     /// must give py so can release GIL while waiting
-    fn run(&mut self, py: Python<'_>) {
-        let stages: Vec<StageConfig> = self.stages.drain(..).collect();
-        let mut handles = Vec::new();
+    ///
+    /// if a stage fails it returns its error and drops both its channel ends,
+    /// upstream stages see a failed send and stop, downstream stages see the
+    /// channel close and finish normally (so sinks still close their files).
+    /// the error is then raised here as otters.PipelineError
+    fn run(&mut self, py: Python<'_>) -> PyResult<()> {
+        let mut running = self.spawn(py)?;
+        running.discard_output();
+        running.finish(py)
+    }
+
+    /// same as run() but returns right away with a PipelineHandle to wait on, cancel
+    /// or watch the progress of
+    fn start(&mut self, py: Python<'_>) -> PyResult<PipelineHandle> {
+        let mut running = self.spawn(py)?;
+        running.discard_output();
+        Ok(PipelineHandle::new(running))
+    }
+
+    /// runs the pipeline and collects what comes out of the last stage into a pyarrow.Table,
+    /// for pipelines without a sink
+    fn run_to_table<'py>(&mut self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        self.check_no_sink()?;
+        let schema = self.schema.clone();
+        let mut running = self.spawn(py)?;
+        let output = running.output.take().expect("checked there's no sink");
+        let mut batches: Vec<RecordBatch> = Vec::new();
+        loop {
+            match running.next_batch(py, &output) {
+                Ok(Some(batch)) => batches.push(batch),
+                Ok(None) => break,
+                Err(interrupt) => {
+                    drop(output);
+                    running.finish(py)?;
+                    return Err(interrupt);
+                }
+            }
+        }
+        running.finish(py)?;
+
+        let schema = batches.first().map(|b| b.schema())
+            .or(schema)
+            .unwrap_or_else(|| Arc::new(Schema::empty()));
+        let table = Table::try_new(batches, schema)
+            .map_err(|e| PipelineError::new_err(format!("batches can't go in one table: {}", e)))?;
+        table.into_pyarrow(py)
+    }
+
+    /// runs the pipeline in the background and hands out what comes out of the last stage
+    /// as pyarrow.RecordBatches. stages only get ahead of the loop by `capacity` batches,
+    /// and breaking out of it stops the pipeline
+    fn iter_batches(&mut self, py: Python<'_>) -> PyResult<BatchIterator> {
+        self.check_no_sink()?;
+        let mut running = self.spawn(py)?;
+        let output = running.output.take().expect("checked there's no sink");
+        Ok(BatchIterator { output, running: Some(running) })
+    }
+}
+
+/// what iter_batches() returns, a python iterator of pyarrow.RecordBatch
+#[pyclass]
+pub struct BatchIterator {
+    output: Receiver<RecordBatch>,
+    /// taken once the output closes, the stages get joined then
+    running: Option<Running>,
+}
+
+#[pymethods]
+impl BatchIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    /// blocks (without the GIL) for the next batch, once the stages are done it raises
+    /// PipelineError if one of them failed and StopIteration otherwise.
+    /// ctrl-c cancels the run
+    fn __next__<'py>(&mut self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyAny>>> {
+        let Some(running) = &self.running else {
+            return Ok(None);
+        };
+        match running.next_batch(py, &self.output)? {
+            Some(batch) => Ok(Some(batch.to_pyarrow(py)?)),
+            None => {
+                self.running.take().unwrap().finish(py)?;
+                Ok(None)
+            }
+        }
+    }
+}
+
+impl Pipeline {
+    /// run_to_table() and iter_batches() take the last stage's output, there has to be one
+    fn check_no_sink(&self) -> PyResult<()> {
+        match self.stages.last() {
+            None => Err(pyo3::exceptions::PyValueError::new_err("the pipeline has no stages")),
+            Some(last) if last.kind.is_sink() => Err(pyo3::exceptions::PyValueError::new_err(
+                "the pipeline ends in a sink, run_to_table() and iter_batches() need it to end in a stage or source"
+            )),
+            Some(_) => Ok(()),
+        }
+    }
+
+    /// checks the stages and spawns a thread for each, Running::join waits for them
+    fn spawn(&mut self, py: Python<'_>) -> PyResult<Running> {
+        let dead_letter_stages = self.stages.iter()
+            .filter(|s| s.on_error == ErrorPolicy::DeadLetter)
+            .count();
+        if dead_letter_stages > 0 && self.dead_letter.is_none() {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "a stage uses on_error=\"dead_letter\" but no dead_letter() target was set"
+            ));
+        }
+
+        let mut stages: Vec<StageConfig> = self.stages.drain(..).collect();
+        self.schema = None;
+
+        // projection pushdown, a parquet source only reads what the stages after it use
+        if let Some(needed) = required_columns(&stages)
+            && let Some(StageConfig { kind: StageKind::ParquetSource(scan), .. }) = stages.first_mut()
+        {
+            scan.project(&needed, false).map_err(|e| e.into_schema_pyerr(0, "parquet_source"))?;
+        }
+        // written into every parquet footer so the files can be traced back to this run
+        let lineage = lineage(py, &stages, self.batch_size, self.capacity);
+        let stage_count = stages.len();
+        self.error_counts.clear();
+        let mut handles: Vec<(usize, &'static str, JoinHandle<StageResult>)> = Vec::new();
         let capacity = self.capacity;
         let batch_size = self.batch_size;
+        let flush_interval = self.flush_interval;
 
-        let has_parquet_source = stages.iter()
-            .any(|s| matches!(s.kind, StageKind::ParquetSource(_)));
+        let has_batch_source = stages.iter().any(|s| s.kind.is_batch_source());
 
         let rust_stage_count = stages.iter()
-            .filter(|s| matches!(s.kind, StageKind::Stage(_) | StageKind::PyTransform(_)))
+            .filter(|s| matches!(s.kind, StageKind::Stage(_) | StageKind::PyTransform { .. }))
             .count();
 
         // batch channels: enough for all rust stages + 1 for source output
@@ -151,7 +871,7 @@ impl Pipeline {
         }
 
         // dict channel only needed for python generator source
-        let dict_channel = if !has_parquet_source {
+        let dict_channel = if !has_batch_source {
             let (tx, rx) = crossbeam_channel::bounded::<Py<PyAny>>(capacity);
             Some((tx, rx))
         } else {
@@ -160,110 +880,450 @@ impl Pipeline {
         let mut dict_tx_opt = dict_channel.as_ref().map(|(tx, _)| Some(tx.clone()));
         let mut dict_rx_opt = dict_channel.map(|(_, rx)| Some(rx));
 
+        // every dead lettering stage holds a clone of the sender,
+        // the original is dropped once all stages are spawned so the channel can close
+        let (mut dead_tx, dead_rx) = if dead_letter_stages > 0 {
+            let (tx, rx) = crossbeam_channel::bounded::<DeadLetter>(capacity);
+            (Some(tx), Some(rx))
+        } else {
+            (None, None)
+        };
+
         let mut batch_chan_idx = 0usize;
+        let cancel = CancelToken::default();
+        let mut progress: Vec<(usize, &'static str, Arc<Progress>)> = Vec::new();
+        let mut verdict: Option<(usize, Sender<bool>)> = None;
 
-        for config in stages.into_iter() {
+        for (idx, config) in stages.into_iter().enumerate() {
+            let kind = config.kind.name();
+            let errors = self.error_handler(idx, kind, config.on_error, dead_tx.as_ref());
+            // sinks don't send anything on, they get what their upstream stage sent them
+            let counts = match progress.last() {
+                Some((_, _, upstream)) if config.kind.is_sink() => upstream.clone(),
+                _ => Arc::new(Progress::default()),
+            };
+            progress.push((idx, kind, counts.clone()));
+            let output = |sender| Output::new(sender, None, counts.clone());
+            // only sources watch for cancel, what they already sent drains through to the sinks
+            let source_output = |sender| Output::new(sender, Some(cancel.clone()), counts.clone());
             match config.kind {
-                StageKind::ParquetSource(path) => {
+                StageKind::ParquetSource(scan) => {
                     // writes directly into batch_channels[0], no batcher needed!! also go GIL needed!
-                    let sender = batch_senders[0].take().unwrap();
+                    let sender = source_output(batch_senders[0].take().unwrap());
+                    batch_chan_idx = 1;
+                    handles.push((idx, kind, spawn_parquet_source(scan, sender, batch_size)));
+                }
+
+                StageKind::CsvSource { path, schema, options } => {
+                    // same as parquet, parses straight into batch_size RecordBatches
+                    let sender = source_output(batch_senders[0].take().unwrap());
+                    batch_chan_idx = 1;
+                    handles.push((idx, kind, spawn_csv_source(path, schema, options, sender, batch_size)));
+                }
+
+                StageKind::PostgresSource { dsn, query, schema } => {
+                    let sender = source_output(batch_senders[0].take().unwrap());
+                    batch_chan_idx = 1;
+                    handles.push((idx, kind, spawn_postgres_source(dsn, query, schema, sender, batch_size)));
+                }
+
+                StageKind::IpcSource(path, format) => {
+                    let sender = source_output(batch_senders[0].take().unwrap());
+                    batch_chan_idx = 1;
+                    handles.push((idx, kind, spawn_ipc_source(path, format, sender, batch_size)));
+                }
+
+                StageKind::ArrowSource(stream) => {
+                    let sender = source_output(batch_senders[0].take().unwrap());
                     batch_chan_idx = 1;
-                    handles.push(spawn_parquet_source(path, sender, batch_size));
+                    handles.push((idx, kind, spawn_arrow_stream_source(stream, sender, batch_size)));
                 }
 
-                StageKind::Source(cb) => {
+                StageKind::Source(cb, schema) => {
                     let dict_tx = dict_tx_opt.as_mut().unwrap().take().unwrap();
                     let dict_rx = dict_rx_opt.as_mut().unwrap().take().unwrap();
+                    let cancel = cancel.clone();
 
-                    handles.push(std::thread::spawn(move || {
-                        let iter = Python::attach(|py| cb.call0(py).unwrap());
-                        loop {
-                            match Python::attach(|py| iter.call_method0(py, "__next__")) {
-                                Ok(item) => { dict_tx.send(item).ok(); }
-                                Err(_) => break,
+                    handles.push((idx, kind, std::thread::spawn(move || {
+                        let iter = Python::attach(|py| cb.call0(py))
+                            .map_err(|e| StageError::from(e).at(0))?;
+                        let mut rows = 0usize;
+                        // a generator waiting on a quiet feed only sees the cancel once it yields
+                        while !cancel.is_cancelled() {
+                            // StopIteration is the normal end, anything else the generator raised is an error
+                            let next = Python::attach(|py| match iter.call_method0(py, "__next__") {
+                                Ok(item) => Ok(Some(item)),
+                                Err(e) if e.is_instance_of::<PyStopIteration>(py) => Ok(None),
+                                Err(e) => Err(e),
+                            }).map_err(|e| StageError::from(e).at(rows / batch_size))?;
+
+                            match next {
+                                Some(item) => {
+                                    if dict_tx.send(item).is_err() {
+                                        break;
+                                    }
+                                }
+                                None => break,
                             }
+                            rows += 1;
                         }
-                    }));
+                        Ok(())
+                    })));
 
-                    let batcher_tx = batch_senders[0].take().unwrap();
-                    handles.push(spawn_batcher(dict_rx, batcher_tx, batch_size));
+                    // the generator thread stops on cancel, the batcher still flushes the rows it has
+                    let batcher_tx = output(batch_senders[0].take().unwrap());
+                    handles.push((idx, kind, spawn_batcher(dict_rx, batcher_tx, batch_size, flush_interval, schema)));
                     batch_chan_idx = 1;
                 }
 
                 StageKind::Stage(mut compute) => {
                     let receiver = batch_receivers[batch_chan_idx - 1].take().unwrap();
-                    let sender = batch_senders[batch_chan_idx].take().unwrap();
+                    let sender = output(batch_senders[batch_chan_idx].take().unwrap());
                     batch_chan_idx += 1;
 
-                    handles.push(std::thread::spawn(move || {
-                        for batch in receiver.iter() {
-                            let result = compute.process(batch);
-                            sender.send(result).ok();
+                    handles.push((idx, kind, std::thread::spawn(move || {
+                        for (n, batch) in receiver.iter().enumerate() {
+                            let input = errors.keeps_input().then(|| batch.clone());
+                            let result = match compute.process(batch) {
+                                Ok(result) => result,
+                                Err(e) => {
+                                    errors.handle(n, input, e)?;
+                                    continue;
+                                }
+                            };
+                            if !sender.send(result) {
+                                break;
+                            }
                         }
-                    }));
+                        Ok(())
+                    })));
                 }
 
-                StageKind::PyTransform(cb) => {
+                StageKind::PyTransform { callback, mode, schema } => {
                     let receiver = batch_receivers[batch_chan_idx - 1].take().unwrap();
-                    let sender = batch_senders[batch_chan_idx].take().unwrap();
+                    let sender = output(batch_senders[batch_chan_idx].take().unwrap());
                     batch_chan_idx += 1;
 
-                    handles.push(std::thread::spawn(move || {
-                        for batch in receiver.iter() {
-                            Python::attach(|py| {
-                                let py_batch = batch.to_pyarrow(py).unwrap();
-                                let rows = py_batch.call_method0("to_pylist").unwrap();
-                                let rows_list = rows.cast::<pyo3::types::PyList>().unwrap();
-
-                                let results: Vec<Py<PyAny>> = rows_list.iter()
-                                    .filter_map(|row| {
-                                        let result = cb.call1(py, (row,)).ok()?;
-                                        if result.is_none(py) { None } else { Some(result) }
-                                    })
-                                    .collect();
-
-                                if !results.is_empty() {
-                                    let pa = py.import("pyarrow").unwrap();
-                                    let rb_class = pa.getattr("RecordBatch").unwrap();
-                                    let pylist = pyo3::types::PyList::new(py, &results).unwrap();
-                                    let new_batch: RecordBatch = RecordBatch::from_pyarrow_bound(
-                                        &rb_class.call_method1("from_pylist", (pylist,)).unwrap()
-                                    ).unwrap();
-                                    sender.send(new_batch).ok();
+                    handles.push((idx, kind, std::thread::spawn(move || {
+                        // row mode without a schema infers types per batch like it always has
+                        let validate = schema.is_some() || mode != CallbackMode::Row;
+                        let mut expected = schema.clone();
+                        for (n, batch) in receiver.iter().enumerate() {
+                            let input = errors.keeps_input().then(|| batch.clone());
+                            // GIL only for the call and converting what it returned
+                            let out = Python::attach(|py| call_transform(py, &callback, mode, schema.as_ref(), &batch))
+                                .and_then(|out| {
+                                    for b in out.iter().filter(|_| validate) {
+                                        match &expected {
+                                            Some(expected) => check_output(expected, b)?,
+                                            None => expected = Some(b.schema()),
+                                        }
+                                    }
+                                    Ok(out)
+                                });
+                            let out = match out {
+                                Ok(out) => out,
+                                Err(e) => {
+                                    errors.handle(n, input, e)?;
+                                    continue;
                                 }
-                            });
+                            };
+
+                            for new_batch in out.into_iter().filter(|b| b.num_rows() > 0) {
+                                if !sender.send(new_batch) {
+                                    return Ok(());
+                                }
+                            }
                         }
-                    }));
+                        Ok(())
+                    })));
                 }
 
-                StageKind::ParquetSink(path) => {
+                StageKind::ParquetSink(path, mut options) => {
+                    options.lineage = Some(lineage.clone());
                     // receives RecordBatches directly, writes to parquet - no GIL yaaay
                     let receiver = batch_receivers[batch_chan_idx - 1].take().unwrap();
-                    handles.push(spawn_parquet_sink(path, receiver));
+                    handles.push((idx, kind, spawn_parquet_sink(path, options, receiver)));
                 }
 
-                StageKind::Sink(cb) => {
+                StageKind::RotatingParquetSink { template, mut options, rotation } => {
+                    options.lineage = Some(lineage.clone());
                     let receiver = batch_receivers[batch_chan_idx - 1].take().unwrap();
-                    handles.push(std::thread::spawn(move || {
-                        for batch in receiver.iter() {
-                            Python::attach(|py| {
-                                let py_batch = batch.to_pyarrow(py).unwrap();
-                                let rows = py_batch.call_method0("to_pylist").unwrap();
-                                let rows_list = rows.cast::<pyo3::types::PyList>().unwrap();
-                                for row in rows_list.iter() {
-                                    cb.call1(py, (row,)).ok();
-                                }
-                            });
-                        }
-                    }));
+                    handles.push((idx, kind, spawn_rotating_parquet_sink(template, options, rotation, receiver)));
+                }
+
+                StageKind::PartitionedParquetSink { root, partition_by, max_open, mut options } => {
+                    options.lineage = Some(lineage.clone());
+                    let receiver = batch_receivers[batch_chan_idx - 1].take().unwrap();
+                    let handle = spawn_partitioned_parquet_sink(root, partition_by, max_open, options, receiver);
+                    handles.push((idx, kind, handle));
+                }
+
+                StageKind::CsvSink(path, options) => {
+                    let receiver = batch_receivers[batch_chan_idx - 1].take().unwrap();
+                    handles.push((idx, kind, spawn_csv_sink(path, options, receiver)));
+                }
+
+                StageKind::PostgresSink { dsn, table } => {
+                    let receiver = batch_receivers[batch_chan_idx - 1].take().unwrap();
+                    // Running tells it whether the stages before it went through, it commits on that
+                    let (tx, upstream_ok) = crossbeam_channel::bounded(1);
+                    verdict = Some((idx, tx));
+                    handles.push((idx, kind, spawn_postgres_sink(dsn, table, receiver, upstream_ok)));
+                }
+
+                StageKind::IpcSink(path, format) => {
+                    let receiver = batch_receivers[batch_chan_idx - 1].take().unwrap();
+                    handles.push((idx, kind, spawn_ipc_sink(path, format, receiver)));
+                }
+
+                StageKind::Sink(cb, mode) => {
+                    let receiver = batch_receivers[batch_chan_idx - 1].take().unwrap();
+                    handles.push((idx, kind, spawn_callback_sink(cb, mode, receiver)));
                 }
             }
         }
 
-        py.detach(|| {
-            for handle in handles {
-                handle.join().unwrap();
+        // stage threads hold their own clones, dropping ours lets the dead letter channel close
+        dead_tx.take();
+        if let Some(rx) = dead_rx {
+            let handle = match self.dead_letter.as_ref() {
+                Some(DeadLetterTarget::Parquet(path)) => {
+                    spawn_dead_letter_parquet(path.clone(), dead_letter_stages > 1, rx)
+                }
+                Some(DeadLetterTarget::Callback(cb)) => {
+                    spawn_dead_letter_callback(cb.clone_ref(py), rx)
+                }
+                None => unreachable!("checked at the top of spawn()"),
+            };
+            handles.push((stage_count, "dead_letter", handle));
+        }
+
+        // still here when the last stage isn't a sink
+        let output = batch_chan_idx.checked_sub(1).and_then(|i| batch_receivers[i].take());
+        Ok(Running::new(handles, output, cancel, progress, verdict))
+    }
+
+    /// registers a stage and carries the known schema through it,
+    /// raising right away if a builtin doesn't fit what comes out of the stage before it
+    fn push_stage(&mut self, config: StageConfig) -> PyResult<()> {
+        let idx = self.stages.len();
+        // spawn() wires the channels source -> stages -> sink, any other order has nowhere to go
+        let misplaced = match self.stages.last() {
+            None if !config.kind.is_source() => Some("the pipeline has to start with a source"),
+            Some(_) if config.kind.is_source() => Some("the pipeline already has a source"),
+            Some(last) if last.kind.is_sink() => Some("nothing can come after a sink"),
+            _ => None,
+        };
+        if let Some(reason) = misplaced {
+            return Err(SchemaError::new_err(format!("stage {} ({}): {}", idx, config.kind.name(), reason)));
+        }
+        match &config.kind {
+            StageKind::ParquetSource(scan) => self.schema = Some(scan.schema.clone()),
+            StageKind::ArrowSource(stream) => self.schema = Some(stream.schema.clone()),
+            StageKind::CsvSource { schema, .. } | StageKind::PostgresSource { schema, .. } => {
+                self.schema = Some(schema.clone());
             }
+            StageKind::IpcSource(path, format) => {
+                // stdin's schema only shows up once run() starts reading it
+                self.schema = if is_stdio(path) {
+                    None
+                } else {
+                    Some(ipc_schema(path, *format).map_err(|e| e.into_schema_pyerr(idx, config.kind.name()))?)
+                };
+            }
+            StageKind::Source(_, schema) => self.schema = schema.clone(),
+            StageKind::PyTransform { schema, .. } => self.schema = schema.clone(),
+            StageKind::Stage(compute) => {
+                if let Some(input) = &self.schema {
+                    let output = compute.output_schema(input)
+                        .map_err(|e| e.into_schema_pyerr(idx, compute.name()))?;
+                    self.schema = Some(Arc::new(output));
+                }
+            }
+            StageKind::PostgresSink { .. } => {
+                // columns postgres can't store fail now instead of after the table is half written
+                if let Some(schema) = &self.schema {
+                    check_schema(schema).map_err(|e| e.into_schema_pyerr(idx, config.kind.name()))?;
+                }
+            }
+            StageKind::ParquetSink(_, options) => {
+                if let Some(schema) = &self.schema {
+                    options.check_schema(schema).map_err(|e| e.into_schema_pyerr(idx, config.kind.name()))?;
+                }
+            }
+            StageKind::RotatingParquetSink { options, rotation, .. } => {
+                if let Some(schema) = &self.schema {
+                    options.check_schema(schema).map_err(|e| e.into_schema_pyerr(idx, config.kind.name()))?;
+                    if let Some(by) = &rotation.by
+                        && schema.field_with_name(by).is_err()
+                    {
+                        let e = StageError::ColumnNotFound(by.clone());
+                        return Err(e.into_schema_pyerr(idx, config.kind.name()));
+                    }
+                }
+            }
+            StageKind::PartitionedParquetSink { partition_by, options, .. } => {
+                if let Some(schema) = &self.schema {
+                    data_schema(schema, partition_by)
+                        .and_then(|data| options.check_schema(&data))
+                        .map_err(|e| e.into_schema_pyerr(idx, config.kind.name()))?;
+                }
+            }
+            StageKind::Sink(..) | StageKind::CsvSink(..) | StageKind::IpcSink(..) => {}
+        }
+        self.stages.push(config);
+        Ok(())
+    }
+
+    /// reads the footers of every file and registers them as one source
+    fn parquet_source(
+        &mut self,
+        files: Vec<ScanFile>,
+        partitions: Vec<Field>,
+        source_file: bool,
+        columns: Option<Vec<String>>,
+        filter: &[Predicate],
+    ) -> PyResult<()> {
+        if files.is_empty() {
+            return Err(pyo3::exceptions::PyValueError::new_err("source() got an empty list of files"));
+        }
+        let mut scan = ParquetScan::new(files, partitions, source_file, filter)
+            .map_err(|e| e.into_schema_pyerr(self.stages.len(), "parquet_source"))?;
+        if let Some(columns) = columns {
+            scan.project(&columns.into_iter().collect(), true)
+                .map_err(|e| e.into_schema_pyerr(self.stages.len(), "parquet_source"))?;
+        }
+        self.push_stage(StageConfig::new(StageKind::ParquetSource(scan)))
+    }
+
+    /// sets up the on_error handling for one stage,
+    /// non fail policies get a counter that error_stats() reads back
+    fn error_handler(
+        &mut self,
+        stage: usize,
+        kind: &'static str,
+        policy: ErrorPolicy,
+        dead_letters: Option<&Sender<DeadLetter>>,
+    ) -> ErrorHandler {
+        let counts = Arc::new(ErrorCounts {
+            stage,
+            kind,
+            skipped: AtomicUsize::new(0),
+            dead_lettered: AtomicUsize::new(0),
         });
+        if policy != ErrorPolicy::Fail {
+            self.error_counts.push(counts.clone());
+        }
+        ErrorHandler {
+            policy,
+            counts,
+            dead_letters: if policy == ErrorPolicy::DeadLetter { dead_letters.cloned() } else { None },
+        }
+    }
+}
+
+/// csv delimiters have to be a single byte, "," or "\t" or "|" etc.
+fn delimiter_byte(delimiter: &str) -> PyResult<u8> {
+    match delimiter.as_bytes() {
+        [b] => Ok(*b),
+        _ => Err(pyo3::exceptions::PyValueError::new_err(format!(
+            "delimiter must be a single ascii character, got '{}'", delimiter
+        ))),
+    }
+}
+
+/// sink(compression=..., compression_level=...) as a parquet codec, None keeps the default
+fn parquet_compression(name: Option<&str>, level: Option<i32>) -> PyResult<Option<Compression>> {
+    let bad_level = |e: parquet::errors::ParquetError| pyo3::exceptions::PyValueError::new_err(e.to_string());
+    let level_u32 = || u32::try_from(level.unwrap_or(-1))
+        .map_err(|_| pyo3::exceptions::PyValueError::new_err("compression_level must be >= 0"));
+    let compression = match (name, level) {
+        (None, None) => return Ok(None),
+        (Some("none" | "uncompressed"), None) => Compression::UNCOMPRESSED,
+        (Some("snappy"), None) => Compression::SNAPPY,
+        (Some("lz4"), None) => Compression::LZ4_RAW,
+        (Some("gzip"), None) => Compression::GZIP(GzipLevel::default()),
+        (Some("gzip"), Some(_)) => Compression::GZIP(GzipLevel::try_new(level_u32()?).map_err(bad_level)?),
+        (Some("brotli"), None) => Compression::BROTLI(BrotliLevel::default()),
+        (Some("brotli"), Some(_)) => Compression::BROTLI(BrotliLevel::try_new(level_u32()?).map_err(bad_level)?),
+        (Some("zstd"), None) => Compression::ZSTD(ZstdLevel::default()),
+        (Some("zstd"), Some(level)) => Compression::ZSTD(ZstdLevel::try_new(level).map_err(bad_level)?),
+        (Some("none" | "uncompressed" | "snappy" | "lz4") | None, Some(_)) => {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "compression_level only works with compression=\"gzip\", \"brotli\" or \"zstd\""
+            ));
+        }
+        (Some(other), _) => {
+            return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "unknown compression '{}', expected none, snappy, gzip, brotli, lz4 or zstd", other
+            )));
+        }
+    };
+    Ok(Some(compression))
+}
+
+/// builtin window sizes and spans are checked when the stage is added, a zscore over fewer
+/// than 2 rows has no std and a 0 window has nothing in it
+fn check_window(name: &str, value: usize, min: usize) -> PyResult<()> {
+    if value < min {
+        return Err(pyo3::exceptions::PyValueError::new_err(format!("{} must be >= {}, got {}", name, min, value)));
+    }
+    Ok(())
+}
+
+/// takes an otters.Schema (anything with to_arrow()) or a pyarrow.Schema
+fn arrow_schema(schema: &Bound<'_, PyAny>) -> PyResult<SchemaRef> {
+    let schema = if schema.hasattr("to_arrow")? {
+        schema.call_method0("to_arrow")?
+    } else {
+        schema.clone()
+    };
+    Ok(Arc::new(Schema::from_pyarrow_bound(&schema)?))
+}
+
+/// the lineage json for parquet footers, see otters.read_lineage
+fn lineage(py: Python<'_>, stages: &[StageConfig], batch_size: usize, capacity: usize) -> String {
+    let stages: Vec<serde_json::Value> = stages.iter()
+        .map(|stage| {
+            let mut described = stage.kind.lineage(py);
+            if matches!(stage.kind, StageKind::Stage(_) | StageKind::PyTransform { .. }) {
+                described["on_error"] = json!(stage.on_error.name());
+            }
+            described
+        })
+        .collect();
+    json!({
+        "otters_version": env!("CARGO_PKG_VERSION"),
+        "started": lineage::timestamp(chrono::Utc::now()),
+        "batch_size": batch_size,
+        "capacity": capacity,
+        "stages": stages,
+    })
+    .to_string()
+}
+
+/// "module.qualname" of a python callable, its repr if it doesn't have those
+fn describe_callable(py: Python<'_>, obj: &Py<PyAny>) -> String {
+    let obj = obj.bind(py);
+    let attr = |name: &str| obj.getattr(name).and_then(|a| a.extract::<String>()).ok();
+    match (attr("__module__"), attr("__qualname__")) {
+        (Some(module), Some(name)) => format!("{}.{}", module, name),
+        (None, Some(name)) => name,
+        _ => obj.repr().map(|r| r.to_string()).unwrap_or_default(),
+    }
+}
+
+/// columns the stages after the source read, walking back from the sink.
+/// None means everything, sinks write whatever reaches them and py_transform could touch anything
+fn required_columns(stages: &[StageConfig]) -> Option<HashSet<String>> {
+    let mut needed = None;
+    for stage in stages.iter().skip(1).rev() {
+        needed = match &stage.kind {
+            StageKind::Stage(compute) => compute.required_columns(needed),
+            StageKind::PyTransform { .. } => None,
+            _ => needed,
+        };
     }
+    needed
 }
\ No newline at end of file
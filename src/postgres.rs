@@ -0,0 +1,53 @@
+use ::postgres::{Client, NoTls};
+use pyo3::prelude::*;
+use pyo3::exceptions::PyValueError;
+use crate::error::StageError;
+
+/// a postgres connection plus what to read or write, made with otters.postgres()
+///
+/// p.source(otters.postgres(dsn, "select ...")) streams a query,
+/// p.sink(otters.postgres(dsn, table="signals")) bulk loads into a table
+#[pyclass(frozen)]
+pub struct Postgres {
+    pub dsn: String,
+    pub query: Option<String>,
+    pub table: Option<String>,
+}
+
+#[pymethods]
+impl Postgres {
+    fn __repr__(&self) -> String {
+        match (&self.query, &self.table) {
+            (Some(query), _) => format!("postgres(query={:?})", query),
+            (_, Some(table)) => format!("postgres(table={:?})", table),
+            _ => "postgres()".to_string(),
+        }
+    }
+}
+
+/// otters.postgres(dsn, query=None, table=None)
+///
+/// dsn is a libpq style "host=localhost user=me dbname=ticks" or postgres:// url
+#[pyfunction]
+#[pyo3(signature = (dsn, query=None, table=None))]
+pub fn postgres(dsn: String, query: Option<String>, table: Option<String>) -> PyResult<Postgres> {
+    if query.is_some() == table.is_some() {
+        return Err(PyValueError::new_err(
+            "postgres() needs either a query (for source()) or table= (for sink())"
+        ));
+    }
+    Ok(Postgres { dsn, query, table })
+}
+
+/// no tls for now, connect through localhost or an ssh tunnel
+pub fn connect(dsn: &str) -> Result<Client, StageError> {
+    Ok(Client::connect(dsn, NoTls)?)
+}
+
+/// "public.signals" -> "public"."signals", quotes doubled so names can't break out
+pub fn quote_ident(name: &str) -> String {
+    name.split('.')
+        .map(|part| format!("\"{}\"", part.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(".")
+}
\ No newline at end of file
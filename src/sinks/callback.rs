@@ -0,0 +1,31 @@
+use arrow::record_batch::RecordBatch;
+use crossbeam_channel::Receiver;
+use pyo3::prelude::*;
+use pyo3::types::PyList;
+use crate::callback::CallbackMode;
+use crate::error::{StageError, StageResult};
+
+/// hands what reaches the end of the pipeline to a python callback,
+/// once per row in row mode, once per batch otherwise
+pub fn spawn_callback_sink(
+    cb: Py<PyAny>,
+    mode: CallbackMode,
+    receiver: Receiver<RecordBatch>,
+) -> std::thread::JoinHandle<StageResult> {
+    std::thread::spawn(move || {
+        for (n, batch) in receiver.iter().enumerate() {
+            Python::attach(|py| -> PyResult<()> {
+                let data = mode.convert(py, &batch)?;
+                if mode == CallbackMode::Row {
+                    for row in data.cast::<PyList>()?.iter() {
+                        cb.call1(py, (row,))?;
+                    }
+                } else {
+                    cb.call1(py, (data,))?;
+                }
+                Ok(())
+            }).map_err(|e| StageError::from(e).at(n))?;
+        }
+        Ok(())
+    })
+}
\ No newline at end of file
@@ -0,0 +1,93 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use arrow::csv::WriterBuilder;
+use arrow::record_batch::RecordBatch;
+use crossbeam_channel::Receiver;
+use flate2::write::GzEncoder;
+use crate::error::{StageError, StageResult};
+use crate::sources::csv_reader::Compression;
+
+/// csv writing options from Pipeline.sink()
+#[derive(Debug, Clone)]
+pub struct CsvWriteOptions {
+    pub delimiter: u8,
+    pub has_header: bool,
+    /// chrono format for timestamp columns, RFC 3339 when not set
+    pub timestamp_format: Option<String>,
+}
+
+/// the file, maybe wrapped in an encoder
+/// kept as an enum so the encoders can be finished properly instead of on drop
+enum Output {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+}
+
+impl Output {
+    fn create(path: &str) -> io::Result<Self> {
+        let file = BufWriter::new(File::create(path)?);
+        Ok(match Compression::from_path(path) {
+            Compression::None => Output::Plain(file),
+            Compression::Gzip => Output::Gzip(GzEncoder::new(file, flate2::Compression::default())),
+            Compression::Zstd => Output::Zstd(zstd::Encoder::new(file, 0)?),
+        })
+    }
+
+    /// writes the gzip/zstd trailer and flushes the file
+    fn finish(self) -> io::Result<()> {
+        let mut file = match self {
+            Output::Plain(file) => file,
+            Output::Gzip(enc) => enc.finish()?,
+            Output::Zstd(enc) => enc.finish()?,
+        };
+        file.flush()
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Plain(w) => w.write(buf),
+            Output::Gzip(w) => w.write(buf),
+            Output::Zstd(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Plain(w) => w.flush(),
+            Output::Gzip(w) => w.flush(),
+            Output::Zstd(w) => w.flush(),
+        }
+    }
+}
+
+/// spawns a thread that writes every batch it receives to a csv file
+/// gzip/zstd is picked from the extension like the csv source
+pub fn spawn_csv_sink(
+    path: String,
+    options: CsvWriteOptions,
+    receiver: Receiver<RecordBatch>,
+) -> std::thread::JoinHandle<StageResult> {
+    std::thread::spawn(move || {
+        let output = Output::create(&path).map_err(|e| StageError::from(e).at(0))?;
+        let mut builder = WriterBuilder::new()
+            .with_header(options.has_header)
+            .with_delimiter(options.delimiter);
+        if let Some(format) = options.timestamp_format {
+            builder = builder.with_timestamp_format(format);
+        }
+        let mut writer = builder.build(output);
+
+        // the header goes out with the first batch, so an empty stream gives an empty file
+        let mut batches = 0;
+        for batch in receiver {
+            writer.write(&batch).map_err(|e| StageError::from(e).at(batches))?;
+            batches += 1;
+        }
+
+        writer.into_inner().finish().map_err(|e| StageError::from(e).at(batches))?;
+        Ok(())
+    })
+}
\ No newline at end of file
@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+use arrow::array::{ArrayRef, StringArray, UInt64Array};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::error::ArrowError;
+use arrow::pyarrow::ToPyArrow;
+use arrow::record_batch::RecordBatch;
+use crossbeam_channel::Receiver;
+use parquet::arrow::ArrowWriter;
+use parquet::file::properties::WriterProperties;
+use pyo3::prelude::*;
+use crate::error::{StageError, StageResult};
+
+/// a batch a stage failed on, routed here by on_error="dead_letter"
+pub struct DeadLetter {
+    pub stage: usize,
+    pub kind: &'static str,
+    pub error: String,
+    pub batch: RecordBatch,
+}
+
+impl DeadLetter {
+    /// the failed input batch with _stage_index, _stage and _error columns tacked on the end
+    pub fn to_batch(&self) -> Result<RecordBatch, ArrowError> {
+        let rows = self.batch.num_rows();
+        let mut fields: Vec<Field> = self.batch.schema().fields().iter()
+            .map(|f| f.as_ref().clone())
+            .collect();
+        fields.push(Field::new("_stage_index", DataType::UInt64, false));
+        fields.push(Field::new("_stage", DataType::Utf8, false));
+        fields.push(Field::new("_error", DataType::Utf8, false));
+
+        let mut columns: Vec<ArrayRef> = self.batch.columns().to_vec();
+        columns.push(Arc::new(UInt64Array::from(vec![self.stage as u64; rows])));
+        columns.push(Arc::new(StringArray::from(vec![self.kind; rows])));
+        columns.push(Arc::new(StringArray::from(vec![self.error.as_str(); rows])));
+
+        RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)
+    }
+}
+
+/// writes dead letters to parquet
+///
+/// different stages see different input schemas so each stage gets its own writer.
+/// if only one stage dead letters the path is used as is, otherwise
+/// "bad.parquet" becomes "bad.stage1.parquet", "bad.stage3.parquet", ...
+///
+/// a stage's input schema can change mid run too (py_transform upstream without schema=),
+/// a bad batch that doesn't match the open file closes it and starts "bad.stage1.2.parquet"
+pub fn spawn_dead_letter_parquet(
+    path: String,
+    split: bool,
+    receiver: Receiver<DeadLetter>,
+) -> std::thread::JoinHandle<StageResult> {
+    std::thread::spawn(move || {
+        // per stage: the open writer, its schema and how many files the stage has had
+        let mut writers: HashMap<usize, (ArrowWriter<File>, SchemaRef, usize)> = HashMap::new();
+
+        for (n, letter) in receiver.iter().enumerate() {
+            let batch = letter.to_batch().map_err(|e| StageError::from(e).at(n))?;
+            // Some(file number) when this batch needs a new file
+            let next = match writers.get(&letter.stage) {
+                Some((_, schema, _)) if *schema == batch.schema() => None,
+                Some((_, _, files)) => Some(files + 1),
+                None => Some(1),
+            };
+            if let Some(files) = next {
+                if let Some((old, _, _)) = writers.remove(&letter.stage) {
+                    old.close().map_err(|e| StageError::from(e).at(n))?;
+                }
+                let target = file_path(&path, split.then_some(letter.stage), files);
+                let file = File::create(&target).map_err(|e| StageError::from(e).at(n))?;
+                let props = WriterProperties::builder().build();
+                let writer = ArrowWriter::try_new(file, batch.schema(), Some(props))
+                    .map_err(|e| StageError::from(e).at(n))?;
+                writers.insert(letter.stage, (writer, batch.schema(), files));
+            }
+            let (writer, _, _) = writers.get_mut(&letter.stage).expect("inserted above");
+            writer.write(&batch).map_err(|e| StageError::from(e).at(n))?;
+        }
+
+        for (_, (w, _, _)) in writers {
+            w.close().map_err(|e| StageError::from(e).at(0))?;
+        }
+        Ok(())
+    })
+}
+
+/// hands each dead letter to a python callback as a pyarrow RecordBatch
+pub fn spawn_dead_letter_callback(
+    cb: Py<PyAny>,
+    receiver: Receiver<DeadLetter>,
+) -> std::thread::JoinHandle<StageResult> {
+    std::thread::spawn(move || {
+        for (n, letter) in receiver.iter().enumerate() {
+            let batch = letter.to_batch().map_err(|e| StageError::from(e).at(n))?;
+            Python::attach(|py| -> PyResult<()> {
+                cb.call1(py, (batch.to_pyarrow(py)?,))?;
+                Ok(())
+            }).map_err(|e| StageError::from(e).at(n))?;
+        }
+        Ok(())
+    })
+}
+
+/// "out/bad.parquet" -> "out/bad.stage2.parquet", its second file "out/bad.stage2.2.parquet"
+fn file_path(path: &str, stage: Option<usize>, file: usize) -> String {
+    if stage.is_none() && file == 1 {
+        return path.to_string();
+    }
+    let p = Path::new(path);
+    let mut name = p.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    if let Some(stage) = stage {
+        name.push_str(&format!(".stage{}", stage));
+    }
+    if file > 1 {
+        name.push_str(&format!(".{}", file));
+    }
+    p.with_file_name(format!("{}.parquet", name)).to_string_lossy().into_owned()
+}
\ No newline at end of file
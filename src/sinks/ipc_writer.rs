@@ -0,0 +1,82 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use arrow::error::ArrowError;
+use arrow::ipc::writer::{FileWriter, StreamWriter};
+use arrow::record_batch::RecordBatch;
+use crossbeam_channel::Receiver;
+use crate::error::{StageError, StageResult};
+use crate::sources::ipc_reader::{IpcFormat, is_stdio};
+
+enum Writer {
+    File(FileWriter<BufWriter<File>>),
+    Stream(StreamWriter<Box<dyn Write + Send>>),
+}
+
+impl Writer {
+    fn create(path: &str, format: IpcFormat, batch: &RecordBatch) -> Result<Self, StageError> {
+        let schema = batch.schema();
+        if is_stdio(path) {
+            let out: Box<dyn Write + Send> = Box::new(BufWriter::new(std::io::stdout()));
+            return Ok(Writer::Stream(StreamWriter::try_new(out, &schema)?));
+        }
+        let file = BufWriter::new(File::create(path)?);
+        Ok(match format {
+            IpcFormat::File => Writer::File(FileWriter::try_new(file, &schema)?),
+            IpcFormat::Stream => {
+                let out: Box<dyn Write + Send> = Box::new(file);
+                Writer::Stream(StreamWriter::try_new(out, &schema)?)
+            }
+        })
+    }
+
+    fn write(&mut self, batch: &RecordBatch) -> Result<(), ArrowError> {
+        match self {
+            Writer::File(w) => w.write(batch),
+            // flushed every batch so whatever reads the other end of a pipe isn't kept waiting
+            Writer::Stream(w) => {
+                w.write(batch)?;
+                w.flush()
+            }
+        }
+    }
+
+    fn finish(&mut self) -> Result<(), ArrowError> {
+        match self {
+            Writer::File(w) => w.finish(),
+            Writer::Stream(w) => {
+                w.finish()?;
+                w.flush()
+            }
+        }
+    }
+}
+
+/// spawns a thread that writes every batch it receives as arrow ipc
+/// batches go out exactly as they are in memory, no re-encoding like parquet
+///
+/// like the parquet sink the writer is created on the first batch,
+/// and a closed channel still finishes the file so it's readable
+pub fn spawn_ipc_sink(
+    path: String,
+    format: IpcFormat,
+    receiver: Receiver<RecordBatch>,
+) -> std::thread::JoinHandle<StageResult> {
+    std::thread::spawn(move || {
+        let mut writer: Option<Writer> = None;
+        let mut batches = 0usize;
+
+        for batch in receiver.iter() {
+            let w = match writer.as_mut() {
+                Some(w) => w,
+                None => writer.insert(Writer::create(&path, format, &batch).map_err(|e| e.at(batches))?),
+            };
+            w.write(&batch).map_err(|e| StageError::from(e).at(batches))?;
+            batches += 1;
+        }
+
+        if let Some(mut w) = writer {
+            w.finish().map_err(|e| StageError::from(e).at(batches))?;
+        }
+        Ok(())
+    })
+}
\ No newline at end of file
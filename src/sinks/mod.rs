@@ -1 +1,8 @@
-pub mod parquet_writer;
\ No newline at end of file
+pub mod parquet_writer;
+pub mod rotating;
+pub mod partitioned;
+pub mod dead_letter;
+pub mod callback;
+pub mod csv_writer;
+pub mod postgres_writer;
+pub mod ipc_writer;
\ No newline at end of file
@@ -1,41 +1,136 @@
+use arrow::datatypes::Schema;
 use arrow::record_batch::RecordBatch;
 use crossbeam_channel::Receiver;
-use parquet::arrow::ArrowWriter;
-use parquet::file::properties::WriterProperties;
+use parquet::arrow::{ArrowSchemaConverter, ArrowWriter};
+use parquet::basic::Compression;
+use parquet::file::metadata::{KeyValue, SortingColumn};
+use parquet::file::properties::{EnabledStatistics, WriterProperties};
+use parquet::schema::types::ColumnPath;
 use std::fs::File;
+use crate::error::{StageError, StageResult};
+use crate::lineage::LINEAGE_KEY;
+
+/// parquet writing options from Pipeline.sink(), None leaves the parquet crate default
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParquetWriteOptions {
+    pub compression: Option<Compression>,
+    /// max rows per row group
+    pub row_group_size: Option<usize>,
+    pub dictionary: Option<bool>,
+    /// false turns off min/max/null count stats, true (default) writes them per page
+    pub statistics: Option<bool>,
+    pub bloom_filter_columns: Vec<String>,
+    /// recorded in the row group metadata as ascending, nulls last. the sink doesn't sort,
+    /// the data has to already be in that order
+    pub sorting_columns: Vec<String>,
+    /// target bytes per data page
+    pub data_page_size: Option<usize>,
+    /// pipeline description json for the footer, filled in by run()
+    pub lineage: Option<String>,
+}
+
+impl ParquetWriteOptions {
+    /// bloom filter / sorting columns have to exist in what gets written,
+    /// called at sink() when the schema is known and again when the file is opened
+    pub fn check_schema(&self, schema: &Schema) -> Result<(), StageError> {
+        for name in self.bloom_filter_columns.iter().chain(&self.sorting_columns) {
+            if schema.field_with_name(name).is_err() {
+                return Err(StageError::ColumnNotFound(name.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    /// writer properties for a file with this schema
+    pub fn properties(&self, schema: &Schema) -> Result<WriterProperties, StageError> {
+        self.check_schema(schema)?;
+        let mut builder = WriterProperties::builder();
+        if let Some(compression) = self.compression {
+            builder = builder.set_compression(compression);
+        }
+        if let Some(rows) = self.row_group_size {
+            builder = builder.set_max_row_group_row_count(Some(rows));
+        }
+        if let Some(dictionary) = self.dictionary {
+            builder = builder.set_dictionary_enabled(dictionary);
+        }
+        if let Some(statistics) = self.statistics {
+            let level = if statistics { EnabledStatistics::Page } else { EnabledStatistics::None };
+            builder = builder.set_statistics_enabled(level);
+        }
+        if let Some(bytes) = self.data_page_size {
+            builder = builder.set_data_page_size_limit(bytes);
+        }
+        for name in &self.bloom_filter_columns {
+            builder = builder.set_column_bloom_filter_enabled(ColumnPath::from(name.as_str()), true);
+        }
+        if !self.sorting_columns.is_empty() {
+            // column_idx is the leaf column in the parquet schema, not the arrow field index
+            let descriptor = ArrowSchemaConverter::new().convert(schema)?;
+            let sorting = self.sorting_columns.iter()
+                .map(|name| {
+                    let idx = descriptor.columns().iter()
+                        .position(|c| c.path().parts() == [name.as_str()])
+                        .ok_or_else(|| StageError::WrongType {
+                            column: name.clone(),
+                            expected: "a top level column",
+                            found: schema.field_with_name(name).unwrap().data_type().clone(),
+                        })?;
+                    Ok(SortingColumn { column_idx: idx as i32, descending: false, nulls_first: false })
+                })
+                .collect::<Result<Vec<_>, StageError>>()?;
+            builder = builder.set_sorting_columns(Some(sorting));
+        }
+        if let Some(lineage) = &self.lineage {
+            let kv = KeyValue::new(LINEAGE_KEY.to_string(), lineage.clone());
+            builder = builder.set_key_value_metadata(Some(vec![kv]));
+        }
+        Ok(builder.build())
+    }
+}
 
 /// spawns back ground thread that receives record batches from pipeline
 /// then writes them to a parquet file.
 /// no python dict conversion, data stays as arrow memory the whole time
+///
+/// if an upstream stage fails the channel just closes early,
+/// so whatever made it through still gets a valid footer
 pub fn spawn_parquet_sink(
     path: String,
+    options: ParquetWriteOptions,
     receiver: Receiver<RecordBatch>,
-) -> std::thread::JoinHandle<()> {
+) -> std::thread::JoinHandle<StageResult> {
     std::thread::spawn(move || {
         // lazily create writer since we don't know final schema until data is here
         let mut writer: Option<ArrowWriter<File>> = None;
+        let mut batches = 0usize;
 
         // receiver.iter() blocks
         // when the upstream channel closes the it ends and the loop exits
         for batch in receiver.iter() {
-            if writer.is_none() {
-                // create the writer lazily on first batch
-                // so we know the schema (which may have new columns added by stages)
-                let file = File::create(&path)
-                    .expect("failed to create output parquet file");
-                let props = WriterProperties::builder().build();
-                writer = Some(
-                    ArrowWriter::try_new(file, batch.schema(), Some(props))
-                        .expect("failed to create parquet writer")
-                );
-            }
-            writer.as_mut().unwrap()
-                .write(&batch)
-                .expect("failed to write batch to parquet");
+            let w = match writer.as_mut() {
+                Some(w) => w,
+                None => {
+                    // create the writer lazily on first batch
+                    // so we know the schema (which may have new columns added by stages)
+                    let props = options.properties(&batch.schema())
+                        .map_err(|e| e.at(batches))?;
+                    let file = File::create(&path)
+                        .map_err(|e| StageError::from(e).at(batches))?;
+                    writer.insert(
+                        ArrowWriter::try_new(file, batch.schema(), Some(props))
+                            .map_err(|e| StageError::from(e).at(batches))?
+                    )
+                }
+            };
+            w.write(&batch)
+                .map_err(|e| StageError::from(e).at(batches))?;
+            batches += 1;
         }
 
-        if let Some(mut w) = writer {
-            w.close().expect("failed to finalize parquet file");
+        if let Some(w) = writer {
+            w.close().map_err(|e| StageError::from(e).at(batches))?;
         }
+        Ok(())
     })
 }
\ No newline at end of file
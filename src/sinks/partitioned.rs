@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::sync::Arc;
+use arrow::array::{Array, StringArray, UInt32Array};
+use arrow::compute::{cast, take_record_batch};
+use arrow::datatypes::{DataType, Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use crossbeam_channel::Receiver;
+use parquet::arrow::ArrowWriter;
+use crate::error::{StageError, StageResult};
+use crate::sinks::parquet_writer::ParquetWriteOptions;
+use crate::sources::hive::{NULL_PARTITION, encode};
+
+/// one value per partition column, None for null
+type Key = Vec<Option<String>>;
+
+/// an open file in one partition directory
+struct Open {
+    writer: ArrowWriter<File>,
+    path: PathBuf,
+    tmp: PathBuf,
+    last_used: u64,
+}
+
+impl Open {
+    fn close(self) -> Result<(), StageError> {
+        self.writer.close()?;
+        fs::rename(&self.tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+/// the file schema, everything except the partition columns (they live in the directory names)
+pub fn data_schema(schema: &Schema, partition_by: &[String]) -> Result<Schema, StageError> {
+    for column in partition_by {
+        schema.field_with_name(column).map_err(|_| StageError::ColumnNotFound(column.clone()))?;
+    }
+    let fields: Vec<_> = schema.fields().iter()
+        .filter(|f| !partition_by.contains(f.name()))
+        .cloned()
+        .collect();
+    if fields.is_empty() {
+        return Err(StageError::Partition("partition_by can't be every column, the files would be empty".to_string()));
+    }
+    Ok(Schema::new_with_metadata(fields, schema.metadata().clone()))
+}
+
+/// writes a hive style dataset, root/symbol=AAPL/date=2025-01-02/part-00000.parquet
+///
+/// rows of every batch get fanned out to one writer per partition. at most `max_open`
+/// files are open at once, when another one is needed the least recently used gets closed,
+/// and if its partition shows up again it gets a new part-N file next to the old one.
+/// every file has the same schema (the first batch's, minus the partition columns) and
+/// is called part-N.parquet.inprogress until it's closed
+pub fn spawn_partitioned_parquet_sink(
+    root: String,
+    partition_by: Vec<String>,
+    max_open: usize,
+    options: ParquetWriteOptions,
+    receiver: Receiver<RecordBatch>,
+) -> std::thread::JoinHandle<StageResult> {
+    std::thread::spawn(move || {
+        let mut open: HashMap<Key, Open> = HashMap::new();
+        // files written so far per partition, for the next part number
+        let mut parts: HashMap<Key, usize> = HashMap::new();
+        let mut schema: Option<(SchemaRef, SchemaRef)> = None;
+        let mut batches = 0usize;
+        // bumped on every partition write, so eviction closes the least recently written
+        // file even when several partitions show up in the same batch
+        let mut writes = 0u64;
+
+        for batch in receiver.iter() {
+            let (full, data) = match &schema {
+                Some(s) => s,
+                None => {
+                    let data = data_schema(&batch.schema(), &partition_by)
+                        .and_then(|d| options.check_schema(&d).map(|_| d))
+                        .map_err(|e| e.at(batches))?;
+                    schema.insert((batch.schema(), Arc::new(data)))
+                }
+            };
+            same_schema(full, &batch.schema()).map_err(|e| e.at(batches))?;
+            let groups = split(&batch, &partition_by).map_err(|e| e.at(batches))?;
+            let batch = batch.project(&data_indices(full, &partition_by))
+                .map_err(|e| StageError::from(e).at(batches))?;
+
+            for (key, rows) in groups {
+                let part = take_record_batch(&batch, &UInt32Array::from(rows))
+                    .map_err(|e| StageError::from(e).at(batches))?;
+
+                if !open.contains_key(&key) {
+                    if open.len() >= max_open {
+                        let oldest = open.iter().min_by_key(|(_, o)| o.last_used).map(|(k, _)| k.clone()).unwrap();
+                        open.remove(&oldest).unwrap().close().map_err(|e| e.at(batches))?;
+                    }
+                    let n = parts.entry(key.clone()).or_insert(0);
+                    let file = create(&root, &partition_by, &key, *n, data, &options).map_err(|e| e.at(batches))?;
+                    *n += 1;
+                    open.insert(key.clone(), file);
+                }
+                let file = open.get_mut(&key).unwrap();
+                writes += 1;
+                file.last_used = writes;
+                file.writer.write(&part)
+                    .map_err(|e| StageError::from(e).in_file(&file.path.to_string_lossy()).at(batches))?;
+            }
+            batches += 1;
+        }
+
+        for (_, file) in open {
+            file.close().map_err(|e| e.at(batches))?;
+        }
+        Ok(())
+    })
+}
+
+fn create(
+    root: &str,
+    partition_by: &[String],
+    key: &Key,
+    n: usize,
+    schema: &SchemaRef,
+    options: &ParquetWriteOptions,
+) -> Result<Open, StageError> {
+    let mut dir = PathBuf::from(root);
+    for (column, value) in partition_by.iter().zip(key) {
+        let value = value.as_deref().map(encode).unwrap_or_else(|| NULL_PARTITION.to_string());
+        dir.push(format!("{}={}", column, value));
+    }
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("part-{:05}.parquet", n));
+    let tmp = path.with_extension("parquet.inprogress");
+    let props = options.properties(schema)?;
+    let writer = ArrowWriter::try_new(File::create(&tmp)?, schema.clone(), Some(props))?;
+    Ok(Open { writer, path, tmp, last_used: 0 })
+}
+
+/// row indices for each partition, in the order the partitions first show up in the batch
+fn split(batch: &RecordBatch, partition_by: &[String]) -> Result<Vec<(Key, Vec<u32>)>, StageError> {
+    // casting to strings once is a lot cheaper than formatting every row
+    let columns = partition_by.iter()
+        .map(|name| {
+            let column = batch.column_by_name(name).ok_or_else(|| StageError::ColumnNotFound(name.clone()))?;
+            Ok(cast(column, &DataType::Utf8)?)
+        })
+        .collect::<Result<Vec<_>, StageError>>()?;
+    let columns: Vec<&StringArray> = columns.iter()
+        .map(|c| c.as_any().downcast_ref::<StringArray>().unwrap())
+        .collect();
+
+    let mut groups: Vec<(Key, Vec<u32>)> = Vec::new();
+    let mut index: HashMap<Key, usize> = HashMap::new();
+    for row in 0..batch.num_rows() {
+        let key: Key = columns.iter()
+            .map(|c| c.is_valid(row).then(|| c.value(row).to_string()))
+            .collect();
+        let i = *index.entry(key.clone()).or_insert_with(|| {
+            groups.push((key, Vec::new()));
+            groups.len() - 1
+        });
+        groups[i].1.push(row as u32);
+    }
+    Ok(groups)
+}
+
+fn data_indices(schema: &Schema, partition_by: &[String]) -> Vec<usize> {
+    schema.fields().iter().enumerate()
+        .filter(|(_, f)| !partition_by.contains(f.name()))
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// every partition's files share one schema, so batches after the first have to match it
+/// (only a py_transform can change the schema mid run)
+fn same_schema(expected: &Schema, found: &Schema) -> Result<(), StageError> {
+    let describe = |s: &Schema| s.fields().iter()
+        .map(|f| format!("{}: {}", f.name(), f.data_type()))
+        .collect::<Vec<_>>();
+    if describe(expected) != describe(found) {
+        return Err(StageError::Partition(format!(
+            "batch has columns {:?}, earlier batches had {:?}", describe(found), describe(expected)
+        )));
+    }
+    Ok(())
+}
\ No newline at end of file
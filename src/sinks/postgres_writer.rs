@@ -0,0 +1,271 @@
+use std::io::Write;
+use arrow::array::{Array, ArrayRef, AsArray};
+use arrow::compute::{CastOptions, cast_with_options};
+use arrow::datatypes::{
+    DataType, Date32Type, Float32Type, Float64Type, Int16Type, Int32Type, Int64Type, Schema,
+    TimeUnit, TimestampMicrosecondType,
+};
+use arrow::record_batch::RecordBatch;
+use crossbeam_channel::Receiver;
+use crate::error::{StageError, StageResult};
+use crate::postgres::{connect, quote_ident};
+
+// postgres counts from 2000-01-01, arrow from 1970-01-01
+const PG_EPOCH_DAYS: i32 = 10_957;
+const PG_EPOCH_MICROS: i64 = PG_EPOCH_DAYS as i64 * 86_400_000_000;
+
+/// how one arrow column goes into postgres
+/// the column is cast to `cast` first so encoding only has to handle a few types
+struct PgColumn {
+    name: String,
+    sql_type: &'static str,
+    cast: DataType,
+}
+
+fn pg_column(name: &str, data_type: &DataType) -> Result<PgColumn, StageError> {
+    let (sql_type, cast) = match data_type {
+        DataType::Boolean => ("boolean", DataType::Boolean),
+        DataType::Int8 | DataType::Int16 | DataType::UInt8 => ("smallint", DataType::Int16),
+        DataType::Int32 | DataType::UInt16 => ("integer", DataType::Int32),
+        DataType::Int64 | DataType::UInt32 | DataType::UInt64 => ("bigint", DataType::Int64),
+        DataType::Float16 | DataType::Float32 => ("real", DataType::Float32),
+        DataType::Float64 => ("double precision", DataType::Float64),
+        DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View => ("text", DataType::Utf8),
+        DataType::Dictionary(_, value) if matches!(**value, DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View) => {
+            ("text", DataType::Utf8)
+        }
+        DataType::Timestamp(_, None) => ("timestamp", DataType::Timestamp(TimeUnit::Microsecond, None)),
+        DataType::Timestamp(_, Some(tz)) => {
+            ("timestamptz", DataType::Timestamp(TimeUnit::Microsecond, Some(tz.clone())))
+        }
+        DataType::Date32 | DataType::Date64 => ("date", DataType::Date32),
+        other => {
+            return Err(StageError::WrongType {
+                column: name.to_string(),
+                expected: "a type postgres can store (bool, int, float, string, timestamp, date)",
+                found: other.clone(),
+            });
+        }
+    };
+    Ok(PgColumn { name: name.to_string(), sql_type, cast })
+}
+
+/// checks every column can be written, used when the sink is registered
+pub fn check_schema(schema: &Schema) -> Result<(), StageError> {
+    for field in schema.fields() {
+        pg_column(field.name(), field.data_type())?;
+    }
+    Ok(())
+}
+
+/// spawns a thread that COPYs every batch it receives into `table`
+///
+/// the table is created from the first batch's schema if it doesn't exist yet.
+/// everything runs in one transaction, once the input closes `upstream_ok` says whether every
+/// stage before this one finished cleanly. if one failed or the run was cancelled the
+/// transaction is rolled back, so the table is left untouched
+pub fn spawn_postgres_sink(
+    dsn: String,
+    table: String,
+    receiver: Receiver<RecordBatch>,
+    upstream_ok: Receiver<bool>,
+) -> std::thread::JoinHandle<StageResult> {
+    std::thread::spawn(move || {
+        let mut client = connect(&dsn).map_err(|e| e.at(0))?;
+        let mut tx = client.transaction().map_err(|e| StageError::from(e).at(0))?;
+
+        // built from the first batch, the schema can't change mid stream
+        let mut columns: Option<(Vec<PgColumn>, String)> = None;
+        let mut batches = 0;
+        for batch in receiver {
+            let n = batches;
+            if columns.is_none() {
+                let cols = batch.schema().fields().iter()
+                    .map(|f| pg_column(f.name(), f.data_type()))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| e.at(n))?;
+                let table = quote_ident(&table);
+                let defs: Vec<String> = cols.iter()
+                    .map(|c| format!("{} {}", quote_ident(&c.name), c.sql_type))
+                    .collect();
+                tx.batch_execute(&format!("CREATE TABLE IF NOT EXISTS {} ({})", table, defs.join(", ")))
+                    .map_err(|e| StageError::from(e).at(n))?;
+                let names: Vec<String> = cols.iter().map(|c| quote_ident(&c.name)).collect();
+                let copy = format!("COPY {} ({}) FROM STDIN (FORMAT binary)", table, names.join(", "));
+                columns = Some((cols, copy));
+            }
+            let (cols, copy) = columns.as_ref().unwrap();
+
+            let data = encode(&batch, cols).map_err(|e| e.at(n))?;
+            let mut writer = tx.copy_in(copy.as_str()).map_err(|e| StageError::from(e).at(n))?;
+            writer.write_all(&data).map_err(|e| StageError::from(e).at(n))?;
+            writer.finish().map_err(|e| StageError::from(e).at(n))?;
+            batches += 1;
+        }
+
+        // the input also closes when a stage upstream fails, what got here is only part of the load
+        if !upstream_ok.recv().unwrap_or(false) {
+            tx.rollback().map_err(|e| StageError::from(e).at(batches))?;
+            return Ok(());
+        }
+        tx.commit().map_err(|e| StageError::from(e).at(batches))?;
+        Ok(())
+    })
+}
+
+/// one batch in postgres' binary COPY format
+/// header, then per row a field count and (length, bytes) per field, -1 length for null
+fn encode(batch: &RecordBatch, columns: &[PgColumn]) -> Result<Vec<u8>, StageError> {
+    // safe: false so out of range values (u64 > i64::MAX...) error instead of turning into nulls
+    let options = CastOptions { safe: false, ..Default::default() };
+    let arrays = batch.columns().iter().zip(columns)
+        .map(|(a, c)| cast_with_options(a, &c.cast, &options))
+        .collect::<Result<Vec<ArrayRef>, _>>()?;
+
+    let mut buf = Vec::with_capacity(batch.num_rows() * arrays.len() * 12 + 21);
+    buf.extend_from_slice(b"PGCOPY\n\xff\r\n\0");
+    buf.extend_from_slice(&0i32.to_be_bytes()); // flags
+    buf.extend_from_slice(&0i32.to_be_bytes()); // header extension length
+
+    for row in 0..batch.num_rows() {
+        buf.extend_from_slice(&(arrays.len() as i16).to_be_bytes());
+        for array in &arrays {
+            if array.is_null(row) {
+                buf.extend_from_slice(&(-1i32).to_be_bytes());
+                continue;
+            }
+            match array.data_type() {
+                DataType::Boolean => field(&mut buf, &[array.as_boolean().value(row) as u8]),
+                DataType::Int16 => field(&mut buf, &array.as_primitive::<Int16Type>().value(row).to_be_bytes()),
+                DataType::Int32 => field(&mut buf, &array.as_primitive::<Int32Type>().value(row).to_be_bytes()),
+                DataType::Int64 => field(&mut buf, &array.as_primitive::<Int64Type>().value(row).to_be_bytes()),
+                DataType::Float32 => field(&mut buf, &array.as_primitive::<Float32Type>().value(row).to_be_bytes()),
+                DataType::Float64 => field(&mut buf, &array.as_primitive::<Float64Type>().value(row).to_be_bytes()),
+                DataType::Utf8 => field(&mut buf, array.as_string::<i32>().value(row).as_bytes()),
+                DataType::Timestamp(_, _) => {
+                    let micros = array.as_primitive::<TimestampMicrosecondType>().value(row);
+                    field(&mut buf, &(micros - PG_EPOCH_MICROS).to_be_bytes())
+                }
+                DataType::Date32 => {
+                    let days = array.as_primitive::<Date32Type>().value(row);
+                    field(&mut buf, &(days - PG_EPOCH_DAYS).to_be_bytes())
+                }
+                other => unreachable!("pg_column never casts to {}", other),
+            }
+        }
+    }
+    buf.extend_from_slice(&(-1i16).to_be_bytes());
+    Ok(buf)
+}
+
+fn field(buf: &mut Vec<u8>, bytes: &[u8]) {
+    buf.extend_from_slice(&(bytes.len() as i32).to_be_bytes());
+    buf.extend_from_slice(bytes);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use arrow::array::{Date32Array, Int64Array, StringArray, TimestampMicrosecondArray};
+    use super::*;
+
+    fn batch() -> RecordBatch {
+        RecordBatch::try_from_iter([
+            ("id", Arc::new(Int64Array::from(vec![Some(7), None])) as ArrayRef),
+            ("sym", Arc::new(StringArray::from(vec![Some("ab"), None])) as ArrayRef),
+            // 2000-01-02 00:00:01 and 1970-01-01
+            ("ts", Arc::new(TimestampMicrosecondArray::from(vec![Some(946_771_201_000_000), Some(0)])) as ArrayRef),
+            ("day", Arc::new(Date32Array::from(vec![Some(PG_EPOCH_DAYS + 1), Some(0)])) as ArrayRef),
+        ]).unwrap()
+    }
+
+    fn columns(batch: &RecordBatch) -> Vec<PgColumn> {
+        batch.schema().fields().iter().map(|f| pg_column(f.name(), f.data_type()).unwrap()).collect()
+    }
+
+    /// reads the next (length, bytes) field, None for a null
+    fn next_field<'a>(data: &mut &'a [u8]) -> Option<&'a [u8]> {
+        let len = i32::from_be_bytes(data[..4].try_into().unwrap());
+        *data = &data[4..];
+        if len < 0 {
+            return None;
+        }
+        let (value, rest) = data.split_at(len as usize);
+        *data = rest;
+        Some(value)
+    }
+
+    #[test]
+    fn encode_binary_copy() {
+        let batch = batch();
+        let data = encode(&batch, &columns(&batch)).unwrap();
+
+        assert_eq!(&data[..11], b"PGCOPY\n\xff\r\n\0");
+        assert_eq!(&data[11..19], &[0u8; 8]);
+        let mut rest = &data[19..];
+
+        // row 1, epochs moved to 2000-01-01
+        assert_eq!(i16::from_be_bytes(rest[..2].try_into().unwrap()), 4);
+        rest = &rest[2..];
+        assert_eq!(next_field(&mut rest), Some(&7i64.to_be_bytes()[..]));
+        assert_eq!(next_field(&mut rest), Some(&b"ab"[..]));
+        assert_eq!(next_field(&mut rest), Some(&(86_401_000_000i64).to_be_bytes()[..]));
+        assert_eq!(next_field(&mut rest), Some(&1i32.to_be_bytes()[..]));
+
+        // row 2, nulls are a -1 length and no bytes, the unix epoch is negative for postgres
+        assert_eq!(i16::from_be_bytes(rest[..2].try_into().unwrap()), 4);
+        rest = &rest[2..];
+        assert_eq!(next_field(&mut rest), None);
+        assert_eq!(next_field(&mut rest), None);
+        assert_eq!(next_field(&mut rest), Some(&(-PG_EPOCH_MICROS).to_be_bytes()[..]));
+        assert_eq!(next_field(&mut rest), Some(&(-PG_EPOCH_DAYS).to_be_bytes()[..]));
+
+        assert_eq!(rest, &(-1i16).to_be_bytes());
+    }
+
+    #[test]
+    fn encode_rejects_out_of_range() {
+        let batch = RecordBatch::try_from_iter([
+            ("n", Arc::new(arrow::array::UInt64Array::from(vec![u64::MAX])) as ArrayRef),
+        ]).unwrap();
+        assert!(encode(&batch, &columns(&batch)).is_err());
+    }
+
+    /// needs a postgres to write to,
+    /// OTTERS_TEST_PG="host=localhost user=postgres" cargo test -- --ignored
+    #[test]
+    #[ignore = "needs OTTERS_TEST_PG"]
+    fn copy_into_postgres() {
+        let dsn = std::env::var("OTTERS_TEST_PG").expect("OTTERS_TEST_PG should be a dsn to write to");
+        let mut client = connect(&dsn).unwrap();
+        client.batch_execute("DROP TABLE IF EXISTS otters_test_sink").unwrap();
+
+        let load = |upstream_ok: bool| {
+            let (tx, rx) = crossbeam_channel::unbounded();
+            let (ok_tx, ok_rx) = crossbeam_channel::bounded(1);
+            tx.send(batch()).unwrap();
+            drop(tx);
+            ok_tx.send(upstream_ok).unwrap();
+            spawn_postgres_sink(dsn.clone(), "otters_test_sink".into(), rx, ok_rx).join().unwrap().unwrap();
+        };
+
+        // a failed upstream rolls back, table and all
+        load(false);
+        let exists: bool = client.query_one("SELECT to_regclass('otters_test_sink') IS NOT NULL", &[])
+            .unwrap().get(0);
+        assert!(!exists);
+
+        load(true);
+        let rows = client.query(
+            "SELECT id, sym, ts::text, day::text FROM otters_test_sink ORDER BY id NULLS LAST", &[],
+        ).unwrap();
+        let rows: Vec<(Option<i64>, Option<String>, String, String)> = rows.iter()
+            .map(|r| (r.get(0), r.get(1), r.get(2), r.get(3)))
+            .collect();
+        assert_eq!(rows, vec![
+            (Some(7), Some("ab".into()), "2000-01-02 00:00:01".into(), "2000-01-02".into()),
+            (None, None, "1970-01-01 00:00:00".into(), "1970-01-01".into()),
+        ]);
+        client.batch_execute("DROP TABLE otters_test_sink").unwrap();
+    }
+}
@@ -0,0 +1,268 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use arrow::array::Array;
+use arrow::compute::kernels::partition::partition;
+use arrow::record_batch::RecordBatch;
+use arrow::util::display::array_value_to_string;
+use chrono::Utc;
+use crossbeam_channel::{Receiver, RecvTimeoutError};
+use parquet::arrow::ArrowWriter;
+use pyo3::prelude::*;
+use pyo3::exceptions::PyValueError;
+use crate::error::{StageError, StageResult};
+use crate::sinks::parquet_writer::ParquetWriteOptions;
+
+/// when the rotating parquet sink closes a file and starts the next one, any mix works
+#[derive(Debug, Clone, Default)]
+pub struct Rotation {
+    /// rows per file, batches get split so every file but the last has exactly this many
+    pub rows: Option<usize>,
+    /// rotate once the file (written + still buffered) gets this big, so files end up a bit over
+    pub bytes: Option<usize>,
+    /// wall clock time a file stays open, checked even when no batches are coming in
+    pub interval: Option<Duration>,
+    /// new file whenever this column's value changes (one file per date...)
+    pub by: Option<String>,
+}
+
+impl Rotation {
+    /// triggers that can start a new file while the rotate_by value stays the same
+    fn splits_runs(&self) -> bool {
+        self.rows.is_some() || self.bytes.is_some() || self.interval.is_some()
+    }
+}
+
+enum Part {
+    Text(String),
+    /// file number, zero padded so the files sort in the order they were written
+    N,
+    /// the rotate_by value
+    Value,
+    /// utc time the file was opened
+    Time,
+}
+
+/// the sink path with {n}, {value} and {time} placeholders
+///
+/// a path without any gets ".{n}" (".{value}" for rotate_by alone, ".{value}.{n}" for
+/// rotate_by plus another trigger) put in front of the extension,
+/// "out/signals.parquet" -> "out/signals.00003.parquet"
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    pub fn parse(path: &str, rotation: &Rotation) -> PyResult<Self> {
+        let path = if path.contains('{') {
+            path.to_string()
+        } else {
+            let placeholder = match &rotation.by {
+                Some(_) if rotation.splits_runs() => "{value}.{n}",
+                Some(_) => "{value}",
+                None => "{n}",
+            };
+            let p = Path::new(path);
+            let stem = p.file_stem().map(|s| s.to_string_lossy()).unwrap_or_default();
+            let name = format!("{}.{}.parquet", stem, placeholder);
+            p.with_file_name(name).to_string_lossy().into_owned()
+        };
+
+        let mut parts = Vec::new();
+        let mut rest = path.as_str();
+        while let Some(start) = rest.find('{') {
+            parts.push(Part::Text(rest[..start].to_string()));
+            let Some(len) = rest[start..].find('}') else {
+                return Err(PyValueError::new_err(format!("unclosed '{{' in sink path '{}'", path)));
+            };
+            parts.push(match &rest[start + 1..start + len] {
+                "n" => Part::N,
+                "value" if rotation.by.is_some() => Part::Value,
+                "value" => return Err(PyValueError::new_err("{value} in the sink path needs rotate_by=")),
+                "time" => Part::Time,
+                other => {
+                    return Err(PyValueError::new_err(format!(
+                        "unknown placeholder {{{}}} in sink path, expected {{n}}, {{value}} or {{time}}", other
+                    )));
+                }
+            });
+            rest = &rest[start + len + 1..];
+        }
+        parts.push(Part::Text(rest.to_string()));
+        // {value} and {time} can come out the same for two files, {n} never does
+        if rotation.splits_runs() && !parts.iter().any(|p| matches!(p, Part::N)) {
+            return Err(PyValueError::new_err(format!(
+                "sink path '{}' needs {{n}} with rotate_rows=, rotate_bytes= or rotate_interval=, \
+                 otherwise two files can get the same name", path
+            )));
+        }
+        Ok(Self { parts })
+    }
+
+    fn render(&self, n: usize, value: Option<&str>) -> String {
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(s) => out.push_str(s),
+                Part::N => out.push_str(&format!("{:05}", n)),
+                // a value with a / in it would turn into directories
+                Part::Value => out.push_str(&value.unwrap_or("null").replace(['/', '\\'], "_")),
+                Part::Time => out.push_str(&Utc::now().format("%Y%m%dT%H%M%S").to_string()),
+            }
+        }
+        out
+    }
+}
+
+/// the path as it was passed to sink() (or with the placeholder that got added)
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for part in &self.parts {
+            match part {
+                Part::Text(s) => write!(f, "{}", s)?,
+                Part::N => write!(f, "{{n}}")?,
+                Part::Value => write!(f, "{{value}}")?,
+                Part::Time => write!(f, "{{time}}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// the file being written, under a .inprogress name until its footer is down
+struct Current {
+    writer: ArrowWriter<File>,
+    path: String,
+    tmp: String,
+    rows: usize,
+    opened: Instant,
+    value: Option<String>,
+}
+
+impl Current {
+    fn close(self) -> Result<(), StageError> {
+        self.writer.close()?;
+        fs::rename(&self.tmp, &self.path)?;
+        Ok(())
+    }
+
+    fn full(&self, rotation: &Rotation) -> bool {
+        rotation.rows.is_some_and(|rows| self.rows >= rows)
+            || rotation.bytes.is_some_and(|bytes| {
+                self.writer.bytes_written() + self.writer.in_progress_size() >= bytes
+            })
+    }
+}
+
+/// parquet sink that splits the output over several files
+///
+/// every file is closed with a valid footer when it rotates, so a crash only loses the one
+/// being written. that one is named "<path>.inprogress" until it's closed, so anything
+/// picking up finished files by their .parquet name never sees half a file
+pub fn spawn_rotating_parquet_sink(
+    template: Template,
+    options: ParquetWriteOptions,
+    rotation: Rotation,
+    receiver: Receiver<RecordBatch>,
+) -> std::thread::JoinHandle<StageResult> {
+    std::thread::spawn(move || {
+        let mut current: Option<Current> = None;
+        let mut written = HashSet::new();
+        let mut files = 0usize;
+        let mut batches = 0usize;
+
+        loop {
+            // with an interval the file gets closed on time even if the feed goes quiet
+            let received = match (&current, rotation.interval) {
+                (Some(c), Some(interval)) => {
+                    receiver.recv_timeout(interval.saturating_sub(c.opened.elapsed()))
+                }
+                _ => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            let batch = match received {
+                Ok(batch) => batch,
+                Err(RecvTimeoutError::Timeout) => {
+                    if let Some(c) = current.take() {
+                        c.close().map_err(|e| e.at(batches))?;
+                    }
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+
+            for (mut slice, value) in runs(&batch, rotation.by.as_deref()).map_err(|e| e.at(batches))? {
+                while slice.num_rows() > 0 {
+                    let stale = current.as_ref().is_some_and(|c| {
+                        c.value != value || rotation.interval.is_some_and(|i| c.opened.elapsed() >= i)
+                    });
+                    if stale {
+                        current.take().unwrap().close().map_err(|e| e.at(batches))?;
+                    }
+                    let c = match current.as_mut() {
+                        Some(c) => c,
+                        None => {
+                            let path = template.render(files, value.as_deref());
+                            if !written.insert(path.clone()) {
+                                // only happens when a rotate_by value comes back after its file closed
+                                let msg = format!("{} was already written this run, put {{n}} in the sink path", path);
+                                return Err(StageError::from(io::Error::new(io::ErrorKind::AlreadyExists, msg)).at(batches));
+                            }
+                            files += 1;
+                            current.insert(open(path, value.clone(), &options, &slice).map_err(|e| e.at(batches))?)
+                        }
+                    };
+
+                    let take = match rotation.rows {
+                        Some(rows) => (rows - c.rows).min(slice.num_rows()),
+                        None => slice.num_rows(),
+                    };
+                    c.writer.write(&slice.slice(0, take)).map_err(|e| StageError::from(e).in_file(&c.path).at(batches))?;
+                    c.rows += take;
+                    slice = slice.slice(take, slice.num_rows() - take);
+
+                    if c.full(&rotation) {
+                        current.take().unwrap().close().map_err(|e| e.at(batches))?;
+                    }
+                }
+            }
+            batches += 1;
+        }
+
+        if let Some(c) = current {
+            c.close().map_err(|e| e.at(batches))?;
+        }
+        Ok(())
+    })
+}
+
+fn open(path: String, value: Option<String>, options: &ParquetWriteOptions, batch: &RecordBatch) -> Result<Current, StageError> {
+    let tmp = format!("{}.inprogress", path);
+    if let Some(dir) = Path::new(&path).parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let props = options.properties(&batch.schema())?;
+    let writer = ArrowWriter::try_new(File::create(&tmp)?, batch.schema(), Some(props))?;
+    Ok(Current { writer, path, tmp, rows: 0, opened: Instant::now(), value })
+}
+
+/// splits a batch where the rotate_by column changes value, the whole batch when there's no column
+fn runs(batch: &RecordBatch, by: Option<&str>) -> Result<Vec<(RecordBatch, Option<String>)>, StageError> {
+    let Some(by) = by else {
+        return Ok(vec![(batch.clone(), None)]);
+    };
+    let column = batch.column_by_name(by)
+        .ok_or_else(|| StageError::ColumnNotFound(by.to_string()))?;
+    partition(std::slice::from_ref(column))?.ranges().into_iter()
+        .map(|range| {
+            let value = if column.is_null(range.start) {
+                None
+            } else {
+                Some(array_value_to_string(column, range.start)?)
+            };
+            Ok((batch.slice(range.start, range.len()), value))
+        })
+        .collect()
+}
\ No newline at end of file
@@ -0,0 +1,96 @@
+use std::sync::Mutex;
+use arrow::compute::concat_batches;
+use arrow::datatypes::SchemaRef;
+use arrow::ffi_stream::ArrowArrayStreamReader;
+use arrow::pyarrow::FromPyArrow;
+use arrow::record_batch::{RecordBatch, RecordBatchIterator, RecordBatchReader};
+use pyo3::prelude::*;
+use crate::channel::Output;
+use crate::error::{StageError, StageResult};
+
+type BatchReader = Box<dyn RecordBatchReader + Send>;
+
+/// a python object that speaks the arrow pycapsule interface, pyarrow Table / RecordBatch /
+/// RecordBatchReader, polars DataFrame, pandas (2.2+) DataFrame, duckdb relation...
+///
+/// the stream is exported when the source is registered (that's where the schema comes from),
+/// batches are only pulled once run() starts. a RecordBatchReader can only be read once
+pub struct ArrowStream {
+    /// behind a mutex because Pipeline has to be Sync, only run() ever takes it
+    reader: Mutex<BatchReader>,
+    pub schema: SchemaRef,
+    /// "pyarrow.lib.Table", "polars.dataframe.frame.DataFrame"... for lineage
+    pub type_name: String,
+}
+
+impl ArrowStream {
+    /// None when obj has neither __arrow_c_stream__ nor __arrow_c_array__
+    pub fn from_py(obj: &Bound<'_, PyAny>) -> PyResult<Option<Self>> {
+        let reader: BatchReader = if obj.hasattr("__arrow_c_stream__")? {
+            Box::new(ArrowArrayStreamReader::from_pyarrow_bound(obj)?)
+        } else if obj.hasattr("__arrow_c_array__")? {
+            let batch = RecordBatch::from_pyarrow_bound(obj)?;
+            let schema = batch.schema();
+            Box::new(RecordBatchIterator::new(vec![Ok(batch)], schema))
+        } else {
+            return Ok(None);
+        };
+        let ty = obj.get_type();
+        Ok(Some(Self {
+            schema: reader.schema(),
+            reader: Mutex::new(reader),
+            type_name: format!("{}.{}", ty.module()?, ty.qualname()?),
+        }))
+    }
+}
+
+/// spawns a thread that pulls the batches through the C stream interface, no GIL unless the
+/// producer needs it (a pyarrow reader wrapping a python generator takes it itself)
+///
+/// batches are re-chunked to batch_size. bigger ones are sliced (zero copy) and small ones
+/// (polars and pandas chunks can be tiny) are concatenated, which is the only copy made
+pub fn spawn_arrow_stream_source(
+    stream: ArrowStream,
+    sender: Output,
+    batch_size: usize,
+) -> std::thread::JoinHandle<StageResult> {
+    std::thread::spawn(move || {
+        let schema = stream.schema;
+        let reader = stream.reader.into_inner().unwrap_or_else(|e| e.into_inner());
+        let mut pending: Vec<RecordBatch> = Vec::new();
+        let mut rows = 0usize;
+        let mut sent = 0usize;
+
+        for batch in reader {
+            let batch = batch.map_err(|e| StageError::from(e).at(sent))?;
+            let mut offset = 0;
+            while offset < batch.num_rows() {
+                let take = (batch_size - rows).min(batch.num_rows() - offset);
+                pending.push(batch.slice(offset, take));
+                rows += take;
+                offset += take;
+                if rows == batch_size {
+                    let out = combine(&schema, std::mem::take(&mut pending)).map_err(|e| e.at(sent))?;
+                    rows = 0;
+                    if !sender.send(out) {
+                        return Ok(());
+                    }
+                    sent += 1;
+                }
+            }
+        }
+        if rows > 0 {
+            let out = combine(&schema, pending).map_err(|e| e.at(sent))?;
+            sender.send(out);
+        }
+        Ok(())
+    })
+}
+
+/// one piece is passed on as is, more get copied into a single batch
+fn combine(schema: &SchemaRef, mut parts: Vec<RecordBatch>) -> Result<RecordBatch, StageError> {
+    if parts.len() == 1 {
+        return Ok(parts.pop().unwrap());
+    }
+    Ok(concat_batches(schema, &parts)?)
+}
\ No newline at end of file
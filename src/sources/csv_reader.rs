@@ -0,0 +1,235 @@
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::sync::Arc;
+use arrow::array::{Array, ArrayRef, AsArray, Int64Array};
+use arrow::compute::cast;
+use arrow::csv::reader::Format;
+use arrow::csv::ReaderBuilder;
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
+use chrono::{NaiveDate, NaiveDateTime};
+use flate2::read::MultiGzDecoder;
+use crate::channel::Output;
+use crate::error::{StageError, StageResult};
+
+/// compression picked from the file extension, shared with the csv sink
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    pub fn from_path(path: &str) -> Self {
+        if path.ends_with(".gz") {
+            Compression::Gzip
+        } else if path.ends_with(".zst") {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+}
+
+/// true for foo.csv, foo.csv.gz and foo.csv.zst
+pub fn is_csv_path(path: &str) -> bool {
+    path.ends_with(".csv") || path.ends_with(".csv.gz") || path.ends_with(".csv.zst")
+}
+
+/// csv parsing options from Pipeline.source()
+#[derive(Debug, Clone)]
+pub struct CsvReadOptions {
+    pub delimiter: u8,
+    pub has_header: bool,
+    /// chrono format for timestamp columns, e.g. "%Y/%m/%d %H:%M:%S%.f"
+    /// without it arrow's own parser is used (ISO 8601 / RFC 3339 style)
+    pub timestamp_format: Option<String>,
+    /// rows to sample when inferring the schema
+    pub infer_rows: usize,
+    /// the timestamp columns timestamp_format applies to, from csv_schema. the ones arrow
+    /// inferred as ISO timestamps by itself are left to arrow's parser
+    pub timestamp_columns: Vec<usize>,
+}
+
+impl CsvReadOptions {
+    fn format(&self) -> Format {
+        Format::default()
+            .with_delimiter(self.delimiter)
+            .with_header(self.has_header)
+    }
+}
+
+/// opens the file and unwraps gzip/zstd, the csv reader only ever sees plain bytes
+fn open(path: &str) -> Result<Box<dyn Read + Send>, StageError> {
+    let file = BufReader::new(File::open(path)?);
+    Ok(match Compression::from_path(path) {
+        Compression::None => Box::new(file),
+        Compression::Gzip => Box::new(MultiGzDecoder::new(file)),
+        Compression::Zstd => Box::new(zstd::Decoder::with_buffer(file)?),
+    })
+}
+
+/// schema the csv source will produce, used at pipeline construction like parquet_schema
+///
+/// an explicit schema is used as is, otherwise the first `infer_rows` rows are sampled.
+/// with timestamp_format set, sampled string columns matching it come out as timestamps
+///
+/// also returns the columns timestamp_format applies to (for CsvReadOptions), every
+/// timestamp column of an explicit schema or the ones promote_timestamps turned into one
+pub fn csv_schema(
+    path: &str,
+    options: &CsvReadOptions,
+    explicit: Option<SchemaRef>,
+) -> Result<(SchemaRef, Vec<usize>), StageError> {
+    if let Some(schema) = explicit {
+        // still open it so a bad path fails at construction like parquet does
+        open(path)?;
+        let timestamps = match options.timestamp_format {
+            Some(_) => schema.fields().iter().enumerate()
+                .filter(|(_, f)| matches!(f.data_type(), DataType::Timestamp(_, _)))
+                .map(|(i, _)| i)
+                .collect(),
+            None => vec![],
+        };
+        return Ok((schema, timestamps));
+    }
+    let (schema, _) = options.format().infer_schema(open(path)?, Some(options.infer_rows))?;
+    let schema = Arc::new(schema);
+    match &options.timestamp_format {
+        Some(format) => promote_timestamps(path, options, schema, format),
+        None => Ok((schema, vec![])),
+    }
+}
+
+/// arrow only infers ISO style timestamps, so with a custom format the string
+/// columns where every sampled value parses get turned into timestamp[us], returned
+/// with the schema
+fn promote_timestamps(
+    path: &str,
+    options: &CsvReadOptions,
+    schema: SchemaRef,
+    format: &str,
+) -> Result<(SchemaRef, Vec<usize>), StageError> {
+    let mut sample = ReaderBuilder::new(schema.clone())
+        .with_format(options.format())
+        .with_batch_size(options.infer_rows.max(1))
+        .build(open(path)?)?;
+    let Some(sample) = sample.next().transpose()? else {
+        return Ok((schema, vec![]));
+    };
+
+    let mut promoted = Vec::new();
+    let fields: Vec<Field> = schema.fields().iter().enumerate()
+        .map(|(i, f)| {
+            let parses = f.data_type() == &DataType::Utf8
+                && sample.column(i).null_count() < sample.num_rows()
+                && sample.column(i).as_string::<i32>().iter().flatten()
+                    .all(|s| parse_timestamp(s, format, &TimeUnit::Microsecond).is_ok());
+            if parses {
+                promoted.push(i);
+                f.as_ref().clone().with_data_type(DataType::Timestamp(TimeUnit::Microsecond, None))
+            } else {
+                f.as_ref().clone()
+            }
+        })
+        .collect();
+    Ok((Arc::new(Schema::new(fields)), promoted))
+}
+
+/// spawns a thread that streams the csv in batch_size chunks into the pipeline
+///
+/// only batch_size rows are parsed at a time so memory stays flat like the parquet source.
+/// with timestamp_format set, the columns in options.timestamp_columns are read as strings
+/// and parsed with chrono
+pub fn spawn_csv_source(
+    path: String,
+    schema: SchemaRef,
+    options: CsvReadOptions,
+    sender: Output,
+    batch_size: usize,
+) -> std::thread::JoinHandle<StageResult> {
+    std::thread::spawn(move || {
+        let timestamp_cols = &options.timestamp_columns;
+
+        // arrow reads the custom format timestamp columns as plain strings first
+        let read_schema = if timestamp_cols.is_empty() {
+            schema.clone()
+        } else {
+            let fields: Vec<Field> = schema.fields().iter().enumerate()
+                .map(|(i, f)| {
+                    if timestamp_cols.contains(&i) {
+                        f.as_ref().clone().with_data_type(DataType::Utf8)
+                    } else {
+                        f.as_ref().clone()
+                    }
+                })
+                .collect();
+            Arc::new(Schema::new(fields))
+        };
+
+        let reader = ReaderBuilder::new(read_schema)
+            .with_format(options.format())
+            .with_batch_size(batch_size)
+            .build(open(&path).map_err(|e| e.at(0))?)
+            .map_err(|e| StageError::from(e).at(0))?;
+
+        for (n, batch) in reader.enumerate() {
+            let mut batch = batch.map_err(|e| StageError::from(e).at(n))?;
+            if let Some(format) = &options.timestamp_format
+                && !timestamp_cols.is_empty()
+            {
+                batch = parse_timestamps(batch, &schema, timestamp_cols, format)
+                    .map_err(|e| StageError::from(e).at(n))?;
+            }
+            if !sender.send(batch) {
+                break;
+            }
+        }
+        Ok(())
+    })
+}
+
+/// swaps the string columns in `cols` for timestamps parsed with a chrono format
+fn parse_timestamps(
+    batch: RecordBatch,
+    schema: &SchemaRef,
+    cols: &[usize],
+    format: &str,
+) -> Result<RecordBatch, ArrowError> {
+    let mut columns: Vec<ArrayRef> = batch.columns().to_vec();
+    for &i in cols {
+        let DataType::Timestamp(unit, _) = schema.field(i).data_type() else {
+            continue;
+        };
+        let strings = columns[i].as_string::<i32>();
+        let mut values = Vec::with_capacity(strings.len());
+        for s in strings.iter() {
+            values.push(match s {
+                Some(s) => Some(parse_timestamp(s, format, unit)?),
+                None => None,
+            });
+        }
+        let ints: ArrayRef = Arc::new(Int64Array::from(values));
+        columns[i] = cast(&ints, schema.field(i).data_type())?;
+    }
+    RecordBatch::try_new(schema.clone(), columns)
+}
+
+/// one timestamp string to an i64 in `unit`, date only formats are taken as midnight
+fn parse_timestamp(s: &str, format: &str, unit: &TimeUnit) -> Result<i64, ArrowError> {
+    let dt = NaiveDateTime::parse_from_str(s, format)
+        .or_else(|_| NaiveDate::parse_from_str(s, format).map(|d| d.and_time(Default::default())))
+        .map_err(|e| ArrowError::ParseError(
+            format!("can't parse '{}' with timestamp format '{}': {}", s, format, e)
+        ))?
+        .and_utc();
+    let value = match unit {
+        TimeUnit::Second => Some(dt.timestamp()),
+        TimeUnit::Millisecond => Some(dt.timestamp_millis()),
+        TimeUnit::Microsecond => Some(dt.timestamp_micros()),
+        TimeUnit::Nanosecond => dt.timestamp_nanos_opt(),
+    };
+    value.ok_or_else(|| ArrowError::ParseError(format!("timestamp '{}' is out of range", s)))
+}
\ No newline at end of file
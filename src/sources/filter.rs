@@ -0,0 +1,163 @@
+use std::cmp::Ordering;
+use pyo3::prelude::*;
+use pyo3::exceptions::PyValueError;
+use pyo3::types::PyList;
+
+/// comparison in one filter term
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    In,
+    NotIn,
+}
+
+impl Op {
+    fn parse(s: &str) -> PyResult<Self> {
+        Ok(match s {
+            "==" | "=" => Op::Eq,
+            "!=" => Op::Ne,
+            "<" => Op::Lt,
+            "<=" => Op::Le,
+            ">" => Op::Gt,
+            ">=" => Op::Ge,
+            "in" => Op::In,
+            "not in" => Op::NotIn,
+            other => {
+                return Err(PyValueError::new_err(format!(
+                    "unknown filter op '{}', expected ==, !=, <, <=, >, >=, in or not in", other
+                )));
+            }
+        })
+    }
+
+    /// back to the string it was parsed from
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Op::Eq => "==",
+            Op::Ne => "!=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+            Op::In => "in",
+            Op::NotIn => "not in",
+        }
+    }
+}
+
+/// literal on the right hand side of a filter term
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Str(String),
+    Int(i64),
+    Float(f64),
+}
+
+impl Value {
+    fn from_py(obj: &Bound<'_, PyAny>) -> PyResult<Self> {
+        // bool is an int subclass in python, not something we filter on
+        if obj.is_instance_of::<pyo3::types::PyBool>() {
+            return Err(PyValueError::new_err("filter values must be str, int or float"));
+        }
+        if let Ok(i) = obj.extract::<i64>() {
+            return Ok(Value::Int(i));
+        }
+        if let Ok(f) = obj.extract::<f64>() {
+            return Ok(Value::Float(f));
+        }
+        if let Ok(s) = obj.extract::<String>() {
+            return Ok(Value::Str(s));
+        }
+        Err(PyValueError::new_err(format!("filter values must be str, int or float, got {}", obj)))
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Int(i) => Some(*i as f64),
+            Value::Float(f) => Some(*f),
+            Value::Str(_) => None,
+        }
+    }
+
+    /// orders a string (hive partition value) against this literal,
+    /// numerically if the literal is a number and the string parses as one
+    fn cmp_str(&self, value: &str) -> Option<Ordering> {
+        match self {
+            Value::Str(s) => Some(value.cmp(s.as_str())),
+            number => value.parse::<f64>().ok()?.partial_cmp(&number.as_f64()?),
+        }
+    }
+}
+
+/// one (column, op, value) term, a filter is a list of these ANDed together
+///
+/// same shape as pyarrow/pandas filters:
+/// [("date", ">=", "2025-01-01"), ("symbol", "in", ["AAPL", "MSFT"])]
+#[derive(Debug, Clone)]
+pub struct Predicate {
+    pub column: String,
+    pub op: Op,
+    /// one value, or the list for in / not in
+    pub values: Vec<Value>,
+}
+
+impl Predicate {
+    /// whether a string value (hive partition) passes, null never does
+    pub fn matches_str(&self, value: Option<&str>) -> bool {
+        let Some(value) = value else {
+            return false;
+        };
+        let equal = |v: &Value| v.cmp_str(value) == Some(Ordering::Equal);
+        match self.op {
+            Op::In => self.values.iter().any(equal),
+            Op::NotIn => !self.values.iter().any(equal),
+            op => {
+                let Some(ord) = self.values[0].cmp_str(value) else {
+                    return false;
+                };
+                match op {
+                    Op::Eq => ord == Ordering::Equal,
+                    Op::Ne => ord != Ordering::Equal,
+                    Op::Lt => ord == Ordering::Less,
+                    Op::Le => ord != Ordering::Greater,
+                    Op::Gt => ord == Ordering::Greater,
+                    Op::Ge => ord != Ordering::Less,
+                    Op::In | Op::NotIn => unreachable!(),
+                }
+            }
+        }
+    }
+}
+
+/// filter=... from source(), a (column, op, value) tuple or a list of them
+pub fn parse_filter(obj: &Bound<'_, PyAny>) -> PyResult<Vec<Predicate>> {
+    let terms: Vec<Bound<'_, PyAny>> = match obj.cast::<PyList>() {
+        Ok(list) => list.iter().collect(),
+        Err(_) => vec![obj.clone()],
+    };
+    terms.iter().map(|term| {
+        let (column, op, value): (String, String, Bound<'_, PyAny>) = term.extract().map_err(|_| {
+            PyValueError::new_err(format!("filter terms must be (column, op, value) tuples, got {}", term))
+        })?;
+        let op = Op::parse(&op)?;
+        let values = if matches!(op, Op::In | Op::NotIn) {
+            if value.is_instance_of::<pyo3::types::PyString>() {
+                return Err(PyValueError::new_err(format!("'{}' needs a list of values", column)));
+            }
+            value.try_iter()?
+                .map(|v| Value::from_py(&v?))
+                .collect::<PyResult<Vec<_>>>()?
+        } else {
+            vec![Value::from_py(&value)?]
+        };
+        if values.is_empty() {
+            return Err(PyValueError::new_err(format!("'{}' got an empty list of values", column)));
+        }
+        Ok(Predicate { column, op, values })
+    }).collect()
+}
\ No newline at end of file
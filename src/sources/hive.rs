@@ -0,0 +1,140 @@
+use std::io;
+use std::path::Path;
+use arrow::datatypes::{DataType, Field};
+use crate::error::StageError;
+use crate::sources::filter::Predicate;
+use crate::sources::parquet_reader::ScanFile;
+
+// what spark/hive/pyarrow write for a null partition value
+pub const NULL_PARTITION: &str = "__HIVE_DEFAULT_PARTITION__";
+
+/// finds the parquet files under a hive style layout (symbol=XYZ/date=2025-01-02/part-0.parquet)
+///
+/// filter terms on partition keys are checked on each key=value directory as the walk goes,
+/// so directories that can't match are never even listed (terms on other columns are left
+/// to the parquet reader). files come back sorted by path,
+/// with one value per partition key, and the key fields (int64 if every value is an integer, else utf8)
+pub fn discover(root: &str, filter: &[Predicate]) -> Result<(Vec<ScanFile>, Vec<Field>), StageError> {
+    let mut found = Vec::new();
+    walk(Path::new(root), &mut vec![], filter, &mut found)?;
+
+    let keys: Vec<String> = match found.first() {
+        Some((_, partition)) => partition.iter().map(|(k, _)| k.clone()).collect(),
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no parquet files under '{}' match the filter", root),
+            ).into());
+        }
+    };
+    for (path, partition) in &found {
+        if !partition.iter().map(|(k, _)| k).eq(keys.iter()) {
+            return Err(StageError::Partition(format!(
+                "'{}' has partition keys {:?}, other files have {:?}",
+                path, partition.iter().map(|(k, _)| k).collect::<Vec<_>>(), keys
+            )));
+        }
+    }
+    let fields = keys.iter().enumerate()
+        .map(|(i, key)| {
+            let values = || found.iter().filter_map(|(_, p)| p[i].1.as_deref());
+            let data_type = if values().all(|v| v.parse::<i64>().is_ok()) {
+                DataType::Int64
+            } else {
+                DataType::Utf8
+            };
+            let nullable = found.iter().any(|(_, p)| p[i].1.is_none());
+            Field::new(key, data_type, nullable)
+        })
+        .collect();
+
+    let files = found.into_iter()
+        .map(|(path, partition)| ScanFile {
+            path,
+            partition: partition.into_iter().map(|(_, v)| v).collect(),
+        })
+        .collect();
+    Ok((files, fields))
+}
+
+type Partition = Vec<(String, Option<String>)>;
+
+fn walk(
+    dir: &Path,
+    partition: &mut Partition,
+    filter: &[Predicate],
+    found: &mut Vec<(String, Partition)>,
+) -> Result<(), StageError> {
+    let mut entries = std::fs::read_dir(dir)?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    entries.sort();
+
+    for path in entries {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        // _SUCCESS, _metadata, .crc files etc.
+        if name.starts_with('_') || name.starts_with('.') {
+            continue;
+        }
+        if !path.is_dir() {
+            if name.ends_with(".parquet") {
+                found.push((path.to_string_lossy().into_owned(), partition.clone()));
+            }
+            continue;
+        }
+
+        let Some((key, value)) = name.split_once('=') else {
+            walk(&path, partition, filter, found)?;
+            continue;
+        };
+        let value = decode(value);
+        let value = (value != NULL_PARTITION).then_some(value);
+        let pruned = filter.iter()
+            .filter(|p| p.column == key)
+            .any(|p| !p.matches_str(value.as_deref()));
+        if pruned {
+            continue;
+        }
+        partition.push((key.to_string(), value));
+        walk(&path, partition, filter, found)?;
+        partition.pop();
+    }
+    Ok(())
+}
+
+/// %XX escapes anything in a partition value that isn't a letter, digit or - _ . ~
+/// so values with / = : spaces etc. still make one directory name
+pub fn encode(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for b in value.bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b'~') {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+/// undoes the %XX escaping writers use for / = etc. in partition values
+fn decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(b)) => {
+                out.push(b);
+                i += 3;
+            }
+            (b, _) => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
\ No newline at end of file
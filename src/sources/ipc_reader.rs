@@ -0,0 +1,83 @@
+use std::fs::File;
+use std::io::BufReader;
+use arrow::datatypes::SchemaRef;
+use arrow::ipc::reader::{FileReader, StreamReader};
+use arrow::record_batch::RecordBatchReader;
+use crate::channel::Output;
+use crate::error::{StageError, StageResult};
+
+/// arrow ipc comes in two flavours
+///
+/// file   - .arrow / .feather / .ipc, has a footer so the schema can be read up front
+/// stream - .arrows or "-" for stdin/stdout, no footer, can be piped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpcFormat {
+    File,
+    Stream,
+}
+
+impl IpcFormat {
+    /// None when the path isn't an ipc path at all
+    pub fn from_path(path: &str) -> Option<Self> {
+        if is_stdio(path) || path.ends_with(".arrows") {
+            Some(IpcFormat::Stream)
+        } else if path.ends_with(".arrow") || path.ends_with(".feather") || path.ends_with(".ipc") {
+            Some(IpcFormat::File)
+        } else {
+            None
+        }
+    }
+}
+
+/// "-" means stdin for a source and stdout for a sink
+pub fn is_stdio(path: &str) -> bool {
+    path == "-"
+}
+
+type BatchReader = Box<dyn RecordBatchReader + Send>;
+
+fn open(path: &str, format: IpcFormat) -> Result<BatchReader, StageError> {
+    if is_stdio(path) {
+        return Ok(Box::new(StreamReader::try_new(BufReader::new(std::io::stdin()), None)?));
+    }
+    let file = BufReader::new(File::open(path)?);
+    Ok(match format {
+        IpcFormat::File => Box::new(FileReader::try_new(file, None)?),
+        IpcFormat::Stream => Box::new(StreamReader::try_new(file, None)?),
+    })
+}
+
+/// reads the footer (file) or the leading schema message (stream) for pipeline construction
+/// stdin isn't touched here, reading it would eat the start of the stream
+pub fn ipc_schema(path: &str, format: IpcFormat) -> Result<SchemaRef, StageError> {
+    Ok(open(path, format)?.schema())
+}
+
+/// spawns a thread that sends the ipc batches downstream as is
+///
+/// batches are already arrow so nothing gets decoded or copied, ones bigger than
+/// batch_size are sliced (also zero copy) so later stages see the usual sizes
+pub fn spawn_ipc_source(
+    path: String,
+    format: IpcFormat,
+    sender: Output,
+    batch_size: usize,
+) -> std::thread::JoinHandle<StageResult> {
+    std::thread::spawn(move || {
+        let reader = open(&path, format).map_err(|e| e.at(0))?;
+        let mut sent = 0usize;
+        for batch in reader {
+            let batch = batch.map_err(|e| StageError::from(e).at(sent))?;
+            let mut offset = 0;
+            while offset < batch.num_rows() {
+                let len = batch_size.min(batch.num_rows() - offset);
+                if !sender.send(batch.slice(offset, len)) {
+                    return Ok(());
+                }
+                offset += len;
+                sent += 1;
+            }
+        }
+        Ok(())
+    })
+}
\ No newline at end of file
@@ -1 +1,8 @@
-pub mod parquet_reader;
\ No newline at end of file
+pub mod parquet_reader;
+pub mod csv_reader;
+pub mod postgres_reader;
+pub mod ipc_reader;
+pub mod arrow_stream;
+pub mod filter;
+pub mod hive;
+pub mod pushdown;
\ No newline at end of file
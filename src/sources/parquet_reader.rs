@@ -1,39 +1,335 @@
-use arrow::record_batch::RecordBatch;
-use crossbeam_channel::Sender;
-use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use std::collections::HashSet;
 use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use arrow::array::{ArrayRef, Int64Array, RecordBatchOptions, StringArray, new_null_array};
+use arrow::compute::{CastOptions, cast_with_options};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::ProjectionMask;
+use parquet::arrow::arrow_reader::{ArrowReaderOptions, ParquetRecordBatchReaderBuilder};
+use parquet::file::metadata::PageIndexPolicy;
+use crate::channel::Output;
+use crate::error::{StageError, StageResult};
+use crate::sources::filter::Predicate;
+use crate::sources::pushdown::{FilterTerm, plan};
+
+/// column added with source(..., source_file=True)
+pub const SOURCE_FILE_COLUMN: &str = "source_file";
+
+/// a file in a scan plus its hive partition values (empty outside of datasets)
+pub struct ScanFile {
+    pub path: String,
+    pub partition: Vec<Option<String>>,
+}
+
+impl From<String> for ScanFile {
+    fn from(path: String) -> Self {
+        ScanFile { path, partition: vec![] }
+    }
+}
+
+/// one or more parquet files streamed back to back as if they were one file
+///
+/// files are read strictly in order so rolling state carries over from one
+/// file (trading day...) into the next
+pub struct ParquetScan {
+    pub files: Vec<ScanFile>,
+    /// union of the file schemas (+ partition keys, source_file), every batch gets conformed to this
+    pub schema: SchemaRef,
+    /// hive partition keys, added as columns after the file's own
+    pub partitions: Vec<Field>,
+    pub source_file: bool,
+    /// file columns to read, None reads them all
+    pub columns: Option<Vec<String>>,
+    /// filter= terms on file columns, partition terms were already used to pick the files
+    pub filter: Vec<FilterTerm>,
+}
+
+impl ParquetScan {
+    /// reads every footer up front and unifies the schemas,
+    /// so a file that doesn't fit fails at construction and not halfway through the run
+    pub fn new(
+        files: Vec<ScanFile>,
+        partitions: Vec<Field>,
+        source_file: bool,
+        filter: &[Predicate],
+    ) -> Result<Self, StageError> {
+        let mut schemas = Vec::with_capacity(files.len());
+        for file in &files {
+            schemas.push(parquet_schema(&file.path).map_err(|e| e.in_file(&file.path))?);
+        }
+        let paths: Vec<&str> = files.iter().map(|f| f.path.as_str()).collect();
+
+        let mut schema = if schemas.len() == 1 {
+            // single file keeps its schema as is, metadata and all
+            schemas[0].as_ref().clone()
+        } else {
+            unify(&paths, &schemas)?
+        };
+
+        let filter = filter.iter()
+            .filter(|p| !partitions.iter().any(|f| f.name() == &p.column))
+            .map(|p| FilterTerm::bind(p, &schema))
+            .collect::<Result<Vec<_>, _>>()?;
 
-/// spawns background thread that reads a parquet file in batches
+        let mut extra = partitions.clone();
+        if source_file {
+            extra.push(Field::new(SOURCE_FILE_COLUMN, DataType::Utf8, false));
+        }
+        if !extra.is_empty() {
+            let mut fields: Vec<Field> = schema.fields().iter().map(|f| f.as_ref().clone()).collect();
+            for field in extra {
+                if schema.field_with_name(field.name()).is_ok() {
+                    return Err(StageError::DuplicateColumn(field.name().clone()));
+                }
+                fields.push(field);
+            }
+            schema = Schema::new_with_metadata(fields, schema.metadata().clone());
+        }
+        Ok(Self { files, schema: Arc::new(schema), partitions, source_file, columns: None, filter })
+    }
+
+    /// only reads the file columns in `keep`, the rest are never decoded
+    ///
+    /// explicit is source(columns=[...]), every name has to exist and partition /
+    /// source_file columns stay. otherwise `keep` is what run() worked out the stages
+    /// need, names that aren't in the files are ignored and unneeded partition columns dropped
+    pub fn project(&mut self, keep: &HashSet<String>, explicit: bool) -> Result<(), StageError> {
+        let is_extra = |name: &str| {
+            (self.source_file && name == SOURCE_FILE_COLUMN)
+                || self.partitions.iter().any(|p| p.name() == name)
+        };
+        if explicit && let Some(missing) = keep.iter().find(|c| is_extra(c) || self.schema.index_of(c).is_err()) {
+            return Err(StageError::ColumnNotFound(missing.clone()));
+        }
+
+        let indices: Vec<usize> = self.schema.fields().iter().enumerate()
+            .filter(|(_, f)| {
+                let name = f.name().as_str();
+                if self.source_file && name == SOURCE_FILE_COLUMN {
+                    true
+                } else if is_extra(name) {
+                    explicit || keep.contains(name)
+                } else {
+                    keep.contains(name)
+                }
+            })
+            .map(|(i, _)| i)
+            .collect();
+        let schema = self.schema.project(&indices)?;
+        self.columns = Some(
+            schema.fields().iter()
+                .map(|f| f.name().clone())
+                .filter(|name| !is_extra(name))
+                .collect()
+        );
+        self.schema = Arc::new(schema);
+        Ok(())
+    }
+}
+
+/// union of the file schemas, columns in order of first appearance
+///
+/// columns missing from some files become nullable and get filled with nulls,
+/// mismatched integer types widen to int64 and other numeric mixes to float64
+fn unify(files: &[&str], schemas: &[SchemaRef]) -> Result<Schema, StageError> {
+    let mut fields: Vec<Field> = Vec::new();
+    for (path, schema) in files.iter().zip(schemas) {
+        for field in schema.fields() {
+            let Some(existing) = fields.iter_mut().find(|f| f.name() == field.name()) else {
+                fields.push(field.as_ref().clone());
+                continue;
+            };
+            let (a, b) = (existing.data_type(), field.data_type());
+            let data_type = if a == b {
+                a.clone()
+            } else if a.is_integer() && b.is_integer() {
+                DataType::Int64
+            } else if a.is_numeric() && b.is_numeric() {
+                DataType::Float64
+            } else {
+                return Err(StageError::Mismatch {
+                    column: field.name().clone(),
+                    expected: a.clone(),
+                    found: b.clone(),
+                }.in_file(path));
+            };
+            let nullable = existing.is_nullable() || field.is_nullable();
+            *existing = existing.clone().with_data_type(data_type).with_nullable(nullable);
+        }
+    }
+
+    for field in fields.iter_mut() {
+        if schemas.iter().any(|s| s.field_with_name(field.name()).is_err()) {
+            *field = field.clone().with_nullable(true);
+        }
+    }
+    Ok(Schema::new(fields))
+}
+
+/// expands what was passed to source() into an ordered list of files
+///
+/// a glob ("ticks/2025-*.parquet") or a directory (every .parquet under it)
+/// comes back sorted so runs are repeatable, a plain path is just itself
+pub fn resolve_paths(path: &str) -> Result<Vec<String>, StageError> {
+    let mut files = if path.contains(['*', '?', '[']) {
+        let paths = glob::glob(path)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("bad glob '{}': {}", path, e)))?;
+        let mut files = Vec::new();
+        for entry in paths {
+            let entry = entry.map_err(io::Error::from)?;
+            if entry.is_file() {
+                files.push(entry.to_string_lossy().into_owned());
+            }
+        }
+        files
+    } else if Path::new(path).is_dir() {
+        let mut files = Vec::new();
+        walk(Path::new(path), &mut files)?;
+        files
+    } else {
+        return Ok(vec![path.to_string()]);
+    };
+
+    if files.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound, format!("no parquet files found for '{}'", path)
+        ).into());
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// every .parquet file under dir, recursively
+fn walk(dir: &Path, files: &mut Vec<String>) -> io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            walk(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == "parquet") {
+            files.push(path.to_string_lossy().into_owned());
+        }
+    }
+    Ok(())
+}
+
+/// spawns background thread that reads the scan's files in batches
 /// then sends each batch into the pipeline channel
 /// returns a join handle so the caller can wait for it ot finish
+///
+/// stops early (without error) if downstream hung up because some later stage failed
 pub fn spawn_parquet_source(
-    path: String,
-    sender: Sender<RecordBatch>,
+    scan: ParquetScan,
+    sender: Output,
     batch_size: usize,
-) -> std::thread::JoinHandle<()> {
+) -> std::thread::JoinHandle<StageResult> {
     std::thread::spawn(move || {
-        let file = File::open(&path)
-            .expect("failed to open parquet file");
-
-        // reads parquet footer metadata (schema, row group offsets)
-        // without loading the row data
-        // with_batch_size controls how many rows come back per batch
-        // which is the key to constant mem usage regardles of filesize
-        let builder = ParquetRecordBatchReaderBuilder::try_new(file)
-            .expect("failed to read parquet metadata")
-            .with_batch_size(batch_size);
-
-        // builds interator
-        let reader = builder.build()
-            .expect("failed to build parquet reader");
-
-        // each it reads on batch from disk then sends it downstream
-        // also handles backpressure
-        for batch in reader {
-            match batch {
-                Ok(b) => { sender.send(b).ok(); }
-                Err(e) => panic!("failed to read parquet batch: {}", e),
+        // batch numbers keep counting across files
+        let mut n = 0usize;
+        for scan_file in &scan.files {
+            let path = scan_file.path.as_str();
+            let file = File::open(path)
+                .map_err(|e| StageError::from(e).in_file(path).at(n))?;
+
+            // reads parquet footer metadata (schema, row group offsets)
+            // without loading the row data
+            // with_batch_size controls how many rows come back per batch
+            // which is the key to constant mem usage regardles of filesize
+            // the page index is only worth loading when there's a filter to check it against
+            let policy = PageIndexPolicy::from(!scan.filter.is_empty());
+            let options = ArrowReaderOptions::new().with_page_index_policy(policy);
+            let mut builder = ParquetRecordBatchReaderBuilder::try_new_with_options(file, options)
+                .map_err(|e| StageError::from(e).in_file(path).at(n))?
+                .with_batch_size(batch_size);
+
+            // predicate pushdown, skips row groups and pages the filter rules out
+            // and filters what's left row by row
+            if !scan.filter.is_empty() {
+                let Some(plan) = plan(&scan.filter, &builder, path)
+                    .map_err(|e| e.in_file(path).at(n))?
+                else {
+                    continue;
+                };
+                builder = builder
+                    .with_row_groups(plan.row_groups)
+                    .with_row_filter(plan.row_filter);
+                if let Some(selection) = plan.selection {
+                    builder = builder.with_row_selection(selection);
+                }
+            }
+
+            // projection pushdown, columns nobody downstream reads are skipped on disk
+            if let Some(columns) = &scan.columns {
+                let roots = builder.schema().fields().iter().enumerate()
+                    .filter(|(_, f)| columns.contains(f.name()))
+                    .map(|(i, _)| i);
+                let mask = ProjectionMask::roots(builder.parquet_schema(), roots);
+                builder = builder.with_projection(mask);
+            }
+
+            // builds interator
+            let reader = builder.build()
+                .map_err(|e| StageError::from(e).in_file(path).at(n))?;
+
+            // each it reads on batch from disk then sends it downstream
+            // also handles backpressure
+            for batch in reader {
+                let batch = batch
+                    .map_err(StageError::from)
+                    .and_then(|b| conform(b, &scan, scan_file))
+                    .map_err(|e| e.in_file(path).at(n))?;
+                if !sender.send(batch) {
+                    return Ok(());
+                }
+                n += 1;
             }
         }
+        Ok(())
     })
+}
+
+/// lines a batch up with the scan schema, filling columns the file doesn't have with nulls
+/// and adding the partition / source_file columns
+fn conform(batch: RecordBatch, scan: &ParquetScan, file: &ScanFile) -> Result<RecordBatch, StageError> {
+    if !scan.source_file && scan.partitions.is_empty() && batch.schema().fields() == scan.schema.fields() {
+        return Ok(batch);
+    }
+    // safe: false so a widening that doesn't fit (u64 -> i64) errors instead of going null
+    let options = CastOptions { safe: false, ..Default::default() };
+    let rows = batch.num_rows();
+    let columns = scan.schema.fields().iter()
+        .map(|field| {
+            if scan.source_file && field.name() == SOURCE_FILE_COLUMN {
+                return Ok(Arc::new(StringArray::from(vec![file.path.as_str(); rows])) as ArrayRef);
+            }
+            if let Some(i) = scan.partitions.iter().position(|p| p.name() == field.name()) {
+                return Ok(partition_column(file.partition[i].as_deref(), field.data_type(), rows));
+            }
+            Ok(match batch.column_by_name(field.name()) {
+                Some(col) if col.data_type() == field.data_type() => col.clone(),
+                Some(col) => cast_with_options(col, field.data_type(), &options)?,
+                None => new_null_array(field.data_type(), rows),
+            })
+        })
+        .collect::<Result<Vec<_>, StageError>>()?;
+    let options = RecordBatchOptions::new().with_row_count(Some(rows));
+    Ok(RecordBatch::try_new_with_options(scan.schema.clone(), columns, &options)?)
+}
+
+/// a hive partition value repeated for every row, discover() only makes int64 and utf8 keys
+fn partition_column(value: Option<&str>, data_type: &DataType, rows: usize) -> ArrayRef {
+    match data_type {
+        DataType::Int64 => Arc::new(Int64Array::from(vec![value.and_then(|v| v.parse().ok()); rows])),
+        _ => Arc::new(StringArray::from(vec![value; rows])),
+    }
+}
+
+/// reads just the footer to get the arrow schema the file will produce
+/// used at pipeline construction so stages can be validated before run()
+pub fn parquet_schema(path: &str) -> Result<SchemaRef, StageError> {
+    let file = File::open(path)?;
+    let builder = ParquetRecordBatchReaderBuilder::try_new(file)?;
+    Ok(builder.schema().clone())
 }
\ No newline at end of file
@@ -0,0 +1,131 @@
+use std::sync::Arc;
+use arrow::array::{
+    ArrayRef, BooleanArray, Date32Array, Float32Array, Float64Array, Int16Array, Int32Array,
+    Int64Array, StringArray, TimestampMicrosecondArray,
+};
+use arrow::datatypes::{DataType, Date32Type, Field, Schema, SchemaRef, TimeUnit};
+use arrow::record_batch::RecordBatch;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use postgres::Row;
+use postgres::types::Type;
+use crate::channel::Output;
+use crate::error::{StageError, StageResult};
+use crate::postgres::connect;
+
+/// arrow type for each postgres type we can read, anything else has to be cast in the query
+fn arrow_type(column: &str, pg_type: &Type) -> Result<DataType, StageError> {
+    let data_type = match pg_type {
+        t if *t == Type::BOOL => DataType::Boolean,
+        t if *t == Type::INT2 => DataType::Int16,
+        t if *t == Type::INT4 => DataType::Int32,
+        t if *t == Type::INT8 => DataType::Int64,
+        t if *t == Type::FLOAT4 => DataType::Float32,
+        t if *t == Type::FLOAT8 => DataType::Float64,
+        t if [Type::TEXT, Type::VARCHAR, Type::BPCHAR, Type::NAME].contains(t) => DataType::Utf8,
+        t if *t == Type::TIMESTAMP => DataType::Timestamp(TimeUnit::Microsecond, None),
+        t if *t == Type::TIMESTAMPTZ => DataType::Timestamp(TimeUnit::Microsecond, Some("+00:00".into())),
+        t if *t == Type::DATE => DataType::Date32,
+        other => {
+            return Err(StageError::UnsupportedPgType {
+                column: column.to_string(),
+                pg_type: other.name().to_string(),
+            });
+        }
+    };
+    Ok(data_type)
+}
+
+/// schema the query will produce, the statement is prepared (not run) at pipeline construction
+pub fn postgres_schema(dsn: &str, query: &str) -> Result<SchemaRef, StageError> {
+    let mut client = connect(dsn)?;
+    let statement = client.prepare(query)?;
+    let fields = statement.columns().iter()
+        .map(|c| Ok(Field::new(c.name(), arrow_type(c.name(), c.type_())?, true)))
+        .collect::<Result<Vec<_>, StageError>>()?;
+    Ok(Arc::new(Schema::new(fields)))
+}
+
+/// spawns a thread that streams the query through a server side cursor (portal),
+/// fetching batch_size rows at a time so the result never has to fit in memory
+pub fn spawn_postgres_source(
+    dsn: String,
+    query: String,
+    schema: SchemaRef,
+    sender: Output,
+    batch_size: usize,
+) -> std::thread::JoinHandle<StageResult> {
+    std::thread::spawn(move || {
+        let mut client = connect(&dsn).map_err(|e| e.at(0))?;
+        // portals only live inside a transaction, it's rolled back on drop which is fine for reads
+        let mut tx = client.transaction().map_err(|e| StageError::from(e).at(0))?;
+        let portal = tx.bind(query.as_str(), &[]).map_err(|e| StageError::from(e).at(0))?;
+
+        let mut n = 0;
+        loop {
+            let rows = tx.query_portal(&portal, batch_size as i32)
+                .map_err(|e| StageError::from(e).at(n))?;
+            if rows.is_empty() {
+                break;
+            }
+            let done = rows.len() < batch_size;
+            let batch = rows_to_batch(&rows, &schema).map_err(|e| e.at(n))?;
+            if !sender.send(batch) {
+                break;
+            }
+            n += 1;
+            if done {
+                break;
+            }
+        }
+        Ok(())
+    })
+}
+
+/// builds one column per schema field out of the fetched rows
+fn rows_to_batch(rows: &[Row], schema: &SchemaRef) -> Result<RecordBatch, StageError> {
+    let columns = schema.fields().iter().enumerate()
+        .map(|(i, field)| column(rows, i, field.data_type()))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(RecordBatch::try_new(schema.clone(), columns)?)
+}
+
+fn column(rows: &[Row], i: usize, data_type: &DataType) -> Result<ArrayRef, StageError> {
+    let array: ArrayRef = match data_type {
+        DataType::Boolean => Arc::new(values::<bool, BooleanArray>(rows, i)?),
+        DataType::Int16 => Arc::new(values::<i16, Int16Array>(rows, i)?),
+        DataType::Int32 => Arc::new(values::<i32, Int32Array>(rows, i)?),
+        DataType::Int64 => Arc::new(values::<i64, Int64Array>(rows, i)?),
+        DataType::Float32 => Arc::new(values::<f32, Float32Array>(rows, i)?),
+        DataType::Float64 => Arc::new(values::<f64, Float64Array>(rows, i)?),
+        DataType::Utf8 => Arc::new(
+            rows.iter().map(|r| r.try_get::<_, Option<&str>>(i)).collect::<Result<StringArray, _>>()?
+        ),
+        DataType::Timestamp(_, None) => Arc::new(
+            rows.iter()
+                .map(|r| Ok(r.try_get::<_, Option<NaiveDateTime>>(i)?.map(|t| t.and_utc().timestamp_micros())))
+                .collect::<Result<TimestampMicrosecondArray, postgres::Error>>()?
+        ),
+        DataType::Timestamp(_, Some(tz)) => Arc::new(
+            rows.iter()
+                .map(|r| Ok(r.try_get::<_, Option<DateTime<Utc>>>(i)?.map(|t| t.timestamp_micros())))
+                .collect::<Result<TimestampMicrosecondArray, postgres::Error>>()?
+                .with_timezone(tz.clone())
+        ),
+        DataType::Date32 => Arc::new(
+            rows.iter()
+                .map(|r| Ok(r.try_get::<_, Option<NaiveDate>>(i)?.map(Date32Type::from_naive_date)))
+                .collect::<Result<Date32Array, postgres::Error>>()?
+        ),
+        other => unreachable!("postgres_schema never produces {}", other),
+    };
+    Ok(array)
+}
+
+/// one nullable column of a plain type
+fn values<T, A>(rows: &[Row], i: usize) -> Result<A, postgres::Error>
+where
+    T: for<'a> postgres::types::FromSql<'a>,
+    A: FromIterator<Option<T>>,
+{
+    rows.iter().map(|r| r.try_get::<_, Option<T>>(i)).collect()
+}
\ No newline at end of file
@@ -0,0 +1,506 @@
+use std::fs::File;
+use std::sync::Arc;
+use arrow::array::{Array, ArrayRef, BooleanArray, Float64Array, Int64Array, Scalar, StringArray};
+use arrow::compute::kernels::boolean::{and_kleene, or_kleene};
+use arrow::compute::kernels::cmp;
+use arrow::compute::{CastOptions, cast_with_options, prep_null_mask_filter};
+use arrow::datatypes::{DataType, Schema};
+use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::ProjectionMask;
+use parquet::arrow::arrow_reader::statistics::StatisticsConverter;
+use parquet::arrow::arrow_reader::{
+    ArrowPredicate, ArrowPredicateFn, ParquetRecordBatchReaderBuilder, RowFilter, RowSelection,
+    RowSelector,
+};
+use parquet::basic::Type as PhysicalType;
+use parquet::bloom_filter::Sbbf;
+use parquet::file::properties::ReaderProperties;
+use parquet::file::reader::FileReader;
+use parquet::file::serialized_reader::{ReadOptionsBuilder, SerializedFileReader};
+use serde_json::json;
+use crate::error::StageError;
+use crate::sources::filter::{Op, Predicate, Value};
+
+/// a filter= term checked against the scan schema, with the literals cast to the column type
+///
+/// used three ways on every file: row group min/max stats (+ bloom filters for ==/in),
+/// page index min/max, and finally row by row on whatever is left
+#[derive(Clone)]
+pub struct FilterTerm {
+    pub column: String,
+    op: Op,
+    data_type: DataType,
+    /// one single value array per literal, already the column's type
+    literals: Vec<ArrayRef>,
+    values: Vec<Value>,
+}
+
+impl FilterTerm {
+    /// fails at construction if the column doesn't exist or a literal can't be its type
+    /// ("2025-01-02T09:30:00" for a timestamp is fine, "abc" for a float isn't)
+    pub fn bind(predicate: &Predicate, schema: &Schema) -> Result<Self, StageError> {
+        let field = schema.field_with_name(&predicate.column)
+            .map_err(|_| StageError::ColumnNotFound(predicate.column.clone()))?;
+        let data_type = match field.data_type() {
+            DataType::Dictionary(_, values) => values.as_ref().clone(),
+            other => other.clone(),
+        };
+        // casting 1.5 to an int column truncates it (qty < 1.5 would become qty < 1),
+        // a fractional literal compares the column as float64 instead
+        let fractional = predicate.values.iter().any(|v| matches!(v, Value::Float(f) if f.fract() != 0.0));
+        let data_type = if data_type.is_integer() && fractional { DataType::Float64 } else { data_type };
+        let literals = predicate.values.iter()
+            .map(|v| literal(v, &data_type))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            column: predicate.column.clone(),
+            op: predicate.op,
+            data_type,
+            literals,
+            values: predicate.values.clone(),
+        })
+    }
+
+    /// the (column, op, value) term as it was passed to source(), for lineage
+    pub fn describe(&self) -> serde_json::Value {
+        let values: Vec<serde_json::Value> = self.values.iter()
+            .map(|v| match v {
+                Value::Str(s) => json!(s),
+                Value::Int(i) => json!(i),
+                Value::Float(f) => json!(f),
+            })
+            .collect();
+        let value = if matches!(self.op, Op::In | Op::NotIn) { json!(values) } else { values[0].clone() };
+        json!([self.column, self.op.as_str(), value])
+    }
+
+    /// files can store a narrower type than the unified schema (int32 vs int64, dictionaries)
+    fn conform(&self, array: &ArrayRef) -> Result<ArrayRef, ArrowError> {
+        if array.data_type() == &self.data_type {
+            Ok(array.clone())
+        } else {
+            arrow::compute::cast(array, &self.data_type)
+        }
+    }
+
+    /// which rows pass, nulls never do
+    fn evaluate(&self, array: &ArrayRef) -> Result<BooleanArray, ArrowError> {
+        let array = self.conform(array)?;
+        let mut result: Option<BooleanArray> = None;
+        for literal in &self.literals {
+            let scalar = Scalar::new(literal.clone());
+            let hit = match self.op {
+                Op::Eq | Op::In => cmp::eq(&array, &scalar)?,
+                Op::Ne | Op::NotIn => cmp::neq(&array, &scalar)?,
+                Op::Lt => cmp::lt(&array, &scalar)?,
+                Op::Le => cmp::lt_eq(&array, &scalar)?,
+                Op::Gt => cmp::gt(&array, &scalar)?,
+                Op::Ge => cmp::gt_eq(&array, &scalar)?,
+            };
+            result = Some(match result {
+                None => hit,
+                Some(acc) if self.op == Op::In => or_kleene(&acc, &hit)?,
+                Some(acc) => and_kleene(&acc, &hit)?,
+            });
+        }
+        // parse_filter doesn't allow empty in lists, so there's always a literal
+        let result = result.unwrap();
+        Ok(if result.null_count() > 0 { prep_null_mask_filter(&result) } else { result })
+    }
+
+    /// false where min/max prove no row can match, true where they can't rule it out
+    fn might_match(&self, mins: &ArrayRef, maxes: &ArrayRef) -> Result<Vec<bool>, ArrowError> {
+        let (mins, maxes) = (self.conform(mins)?, self.conform(maxes)?);
+        let first = Scalar::new(self.literals[0].clone());
+        let might = match self.op {
+            Op::Eq | Op::In => {
+                let mut any: Option<BooleanArray> = None;
+                for literal in &self.literals {
+                    let scalar = Scalar::new(literal.clone());
+                    let in_range = and_kleene(&cmp::lt_eq(&mins, &scalar)?, &cmp::gt_eq(&maxes, &scalar)?)?;
+                    any = Some(match any {
+                        None => in_range,
+                        Some(acc) => or_kleene(&acc, &in_range)?,
+                    });
+                }
+                any.unwrap()
+            }
+            Op::Lt => cmp::lt(&mins, &first)?,
+            Op::Le => cmp::lt_eq(&mins, &first)?,
+            Op::Gt => cmp::gt(&maxes, &first)?,
+            Op::Ge => cmp::gt_eq(&maxes, &first)?,
+            // would need min == max == value, rarely prunes anything
+            Op::Ne | Op::NotIn => return Ok(vec![true; mins.len()]),
+        };
+        // missing stats come back null and can't rule anything out
+        Ok(might.iter().map(|m| m.unwrap_or(true)).collect())
+    }
+}
+
+/// one literal as a single value array of `data_type`
+fn literal(value: &Value, data_type: &DataType) -> Result<ArrayRef, StageError> {
+    let array: ArrayRef = match value {
+        Value::Str(s) => Arc::new(StringArray::from(vec![s.as_str()])),
+        Value::Int(i) => Arc::new(Int64Array::from(vec![*i])),
+        Value::Float(f) => Arc::new(Float64Array::from(vec![*f])),
+    };
+    // safe: false so a literal that doesn't fit the column errors instead of turning null
+    let options = CastOptions { safe: false, ..Default::default() };
+    Ok(cast_with_options(&array, data_type, &options)?)
+}
+
+/// what's left to read in a file once the filter has been checked against its metadata
+pub struct FilePlan {
+    pub row_groups: Vec<usize>,
+    pub selection: Option<RowSelection>,
+    pub row_filter: RowFilter,
+}
+
+/// prunes a file with the filter, None when nothing in it can match
+///
+/// row groups go first (min/max stats, null counts, bloom filters), then pages inside
+/// the remaining row groups if the file has a page index, and rows in surviving pages
+/// get the row filter, which only decodes the other columns for rows that pass
+pub fn plan(
+    terms: &[FilterTerm],
+    builder: &ParquetRecordBatchReaderBuilder<File>,
+    path: &str,
+) -> Result<Option<FilePlan>, StageError> {
+    let metadata = builder.metadata();
+    let arrow_schema = builder.schema();
+    let parquet_schema = builder.parquet_schema();
+    let groups = metadata.row_groups();
+
+    let mut keep = vec![true; groups.len()];
+    let mut converters = Vec::with_capacity(terms.len());
+    for term in terms {
+        // a column this file doesn't have is all nulls, and nulls never match
+        if arrow_schema.field_with_name(&term.column).is_err() {
+            return Ok(None);
+        }
+        let converter = StatisticsConverter::try_new(&term.column, arrow_schema, parquet_schema)?;
+        let mins = converter.row_group_mins(groups.iter())?;
+        let maxes = converter.row_group_maxes(groups.iter())?;
+        let nulls = converter.row_group_null_counts(groups.iter())?;
+        for (i, might) in term.might_match(&mins, &maxes)?.into_iter().enumerate() {
+            let all_null = nulls.is_valid(i) && nulls.value(i) == groups[i].num_rows() as u64;
+            keep[i] &= might && !all_null;
+        }
+        converters.push(converter);
+    }
+    let mut row_groups: Vec<usize> = (0..groups.len()).filter(|&i| keep[i]).collect();
+
+    bloom_prune(terms, &converters, path, &mut row_groups)?;
+    if row_groups.is_empty() {
+        return Ok(None);
+    }
+
+    let mut selection: Option<RowSelection> = None;
+    if let (Some(column_index), Some(offset_index)) = (metadata.column_index(), metadata.offset_index()) {
+        for (term, converter) in terms.iter().zip(&converters) {
+            let Some(counts) = converter.data_page_row_counts(offset_index, groups, &row_groups)? else {
+                continue;
+            };
+            let mins = converter.data_page_mins(column_index, offset_index, &row_groups)?;
+            let maxes = converter.data_page_maxes(column_index, offset_index, &row_groups)?;
+            let might = term.might_match(&mins, &maxes)?;
+            if might.len() != counts.len() || counts.null_count() > 0 {
+                continue;
+            }
+            let selectors: Vec<RowSelector> = might.iter().zip(counts.values())
+                .map(|(&might, &rows)| {
+                    if might { RowSelector::select(rows as usize) } else { RowSelector::skip(rows as usize) }
+                })
+                .collect();
+            let pages = RowSelection::from(selectors);
+            selection = Some(match selection {
+                Some(acc) => acc.intersection(&pages),
+                None => pages,
+            });
+        }
+    }
+    if let Some(selection) = &selection
+        && !selection.selects_any()
+    {
+        return Ok(None);
+    }
+
+    let predicates = terms.iter()
+        .map(|term| {
+            let index = arrow_schema.index_of(&term.column)?;
+            let mask = ProjectionMask::roots(parquet_schema, [index]);
+            let term = term.clone();
+            let predicate = ArrowPredicateFn::new(mask, move |batch: RecordBatch| term.evaluate(batch.column(0)));
+            Ok(Box::new(predicate) as Box<dyn ArrowPredicate>)
+        })
+        .collect::<Result<Vec<_>, ArrowError>>()?;
+
+    Ok(Some(FilePlan { row_groups, selection, row_filter: RowFilter::new(predicates) }))
+}
+
+/// drops row groups whose bloom filters say none of the == / in values are there
+fn bloom_prune(
+    terms: &[FilterTerm],
+    converters: &[StatisticsConverter<'_>],
+    path: &str,
+    row_groups: &mut Vec<usize>,
+) -> Result<(), StageError> {
+    let lookups: Vec<(&FilterTerm, usize)> = terms.iter().zip(converters)
+        .filter(|(term, _)| matches!(term.op, Op::Eq | Op::In))
+        .filter_map(|(term, converter)| Some((term, converter.parquet_column_index()?)))
+        .collect();
+    if lookups.is_empty() || row_groups.is_empty() {
+        return Ok(());
+    }
+
+    let properties = ReaderProperties::builder().set_read_bloom_filter(true).build();
+    let options = ReadOptionsBuilder::new().with_reader_properties(properties).build();
+    let reader = SerializedFileReader::new_with_options(File::open(path)?, options)?;
+
+    let mut kept = Vec::with_capacity(row_groups.len());
+    for &rg in row_groups.iter() {
+        let group = reader.get_row_group(rg)?;
+        let might = lookups.iter().all(|(term, column)| {
+            let Some(bloom) = group.get_column_bloom_filter(*column) else {
+                return true;
+            };
+            let physical = group.metadata().column(*column).column_type();
+            term.values.iter().any(|v| bloom_check(bloom, v, &term.data_type, physical))
+        });
+        if might {
+            kept.push(rg);
+        }
+    }
+    *row_groups = kept;
+    Ok(())
+}
+
+/// true if the value might be in the row group, anything we can't hash the way the writer did is a maybe
+fn bloom_check(bloom: &Sbbf, value: &Value, data_type: &DataType, physical: PhysicalType) -> bool {
+    match (value, data_type, physical) {
+        (Value::Str(s), DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View, PhysicalType::BYTE_ARRAY) => {
+            bloom.check(&s.as_str())
+        }
+        (Value::Int(i), DataType::Int64, PhysicalType::INT64) => bloom.check(i),
+        // int32 files unified into int64 still hash as i32, the literal cast already checked int32 columns
+        (Value::Int(i), DataType::Int32 | DataType::Int64, PhysicalType::INT32) => match i32::try_from(*i) {
+            Ok(i) => bloom.check(&i),
+            Err(_) => false,
+        },
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+    use arrow::array::Int32Array;
+    use arrow::compute::{concat_batches, filter_record_batch};
+    use arrow::datatypes::Field;
+    use parquet::arrow::ArrowWriter;
+    use parquet::arrow::arrow_reader::ArrowReaderOptions;
+    use parquet::file::metadata::PageIndexPolicy;
+    use parquet::file::properties::WriterProperties;
+    use parquet::schema::types::ColumnPath;
+    use super::*;
+
+    /// 1000 rows in row groups of 100 and pages of 10. id is sorted so stats prune it,
+    /// sym and code repeat in every row group so only their bloom filters can
+    fn write_file(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join("otters_pushdown_tests");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("{}.parquet", name));
+
+        let ids: Vec<i64> = (0..1000).collect();
+        let batch = RecordBatch::try_from_iter([
+            ("id", Arc::new(Int64Array::from(ids.clone())) as ArrayRef),
+            ("sym", Arc::new(StringArray::from_iter_values(ids.iter().map(|i| ["AAPL", "NVDA", "TSLA"][*i as usize % 3]))) as ArrayRef),
+            ("px", Arc::new(Float64Array::from_iter(ids.iter().map(|i| (i % 7 != 0).then_some(100.0 + *i as f64 * 0.5)))) as ArrayRef),
+            // even numbers only, an odd one is inside every row group's min/max
+            ("code", Arc::new(Int32Array::from_iter_values(ids.iter().map(|i| (*i as i32 % 50) * 2))) as ArrayRef),
+        ]).unwrap();
+
+        let props = WriterProperties::builder()
+            .set_max_row_group_row_count(Some(100))
+            .set_data_page_row_count_limit(10)
+            .set_write_batch_size(10)
+            .set_column_bloom_filter_enabled(ColumnPath::from("sym"), true)
+            .set_column_bloom_filter_enabled(ColumnPath::from("code"), true)
+            .build();
+        let mut writer = ArrowWriter::try_new(File::create(&path).unwrap(), batch.schema(), Some(props)).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+        path
+    }
+
+    fn term(schema: &Schema, column: &str, op: Op, values: Vec<Value>) -> FilterTerm {
+        FilterTerm::bind(&Predicate { column: column.to_string(), op, values }, schema).unwrap()
+    }
+
+    fn builder(path: &Path) -> ParquetRecordBatchReaderBuilder<File> {
+        let options = ArrowReaderOptions::new().with_page_index_policy(PageIndexPolicy::from(true));
+        ParquetRecordBatchReaderBuilder::try_new_with_options(File::open(path).unwrap(), options).unwrap()
+    }
+
+    /// read the way the parquet source does it, None when plan() skips the whole file
+    fn filtered_read(path: &Path, terms: &[FilterTerm]) -> Option<(Vec<usize>, Option<RowSelection>, RecordBatch)> {
+        let builder = builder(path);
+        let schema = builder.schema().clone();
+        let plan = plan(terms, &builder, path.to_str().unwrap()).unwrap()?;
+        let mut builder = builder
+            .with_row_groups(plan.row_groups.clone())
+            .with_row_filter(plan.row_filter);
+        if let Some(selection) = plan.selection.clone() {
+            builder = builder.with_row_selection(selection);
+        }
+        let batches = builder.build().unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+        Some((plan.row_groups, plan.selection, concat_batches(&schema, &batches).unwrap()))
+    }
+
+    /// everything read, then the same filter applied row by row
+    fn unfiltered_read(path: &Path, terms: &[FilterTerm]) -> RecordBatch {
+        let builder = builder(path);
+        let schema = builder.schema().clone();
+        let batches = builder.build().unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+        let mut batch = concat_batches(&schema, &batches).unwrap();
+        for term in terms {
+            let mask = term.evaluate(batch.column_by_name(&term.column).unwrap()).unwrap();
+            batch = filter_record_batch(&batch, &mask).unwrap();
+        }
+        batch
+    }
+
+    fn assert_same_rows(path: &Path, terms: &[FilterTerm]) {
+        let expected = unfiltered_read(path, terms);
+        match filtered_read(path, terms) {
+            Some((_, _, batch)) => assert_eq!(batch, expected),
+            None => assert_eq!(expected.num_rows(), 0, "plan skipped a file with matching rows"),
+        }
+    }
+
+    #[test]
+    fn filtered_read_matches_unfiltered() {
+        let path = write_file("stats");
+        let schema = builder(&path).schema().clone();
+        let cases = vec![
+            vec![term(&schema, "id", Op::Ge, vec![Value::Int(950)])],
+            vec![term(&schema, "id", Op::Lt, vec![Value::Int(25)])],
+            vec![term(&schema, "id", Op::Le, vec![Value::Int(0)])],
+            vec![term(&schema, "id", Op::Gt, vec![Value::Int(999)])],
+            vec![term(&schema, "id", Op::Eq, vec![Value::Int(555)])],
+            vec![term(&schema, "id", Op::In, vec![Value::Int(3), Value::Int(777)])],
+            vec![term(&schema, "id", Op::Ne, vec![Value::Int(5)])],
+            vec![term(&schema, "id", Op::NotIn, vec![Value::Int(5), Value::Int(6)])],
+            vec![term(&schema, "px", Op::Gt, vec![Value::Float(590.5)])],
+            vec![term(&schema, "px", Op::Le, vec![Value::Int(101)])],
+            vec![term(&schema, "sym", Op::Eq, vec![Value::Str("NVDA".into())])],
+            vec![
+                term(&schema, "id", Op::Ge, vec![Value::Int(200)]),
+                term(&schema, "id", Op::Lt, vec![Value::Int(420)]),
+                term(&schema, "sym", Op::In, vec![Value::Str("AAPL".into()), Value::Str("TSLA".into())]),
+                term(&schema, "px", Op::Ge, vec![Value::Float(250.0)]),
+            ],
+            // fractional values on int columns
+            vec![term(&schema, "id", Op::Lt, vec![Value::Float(1.5)])],
+            vec![term(&schema, "id", Op::Eq, vec![Value::Float(1.5)])],
+            vec![term(&schema, "id", Op::In, vec![Value::Float(2.5), Value::Int(777)])],
+            vec![term(&schema, "code", Op::Ge, vec![Value::Float(97.5)])],
+            vec![term(&schema, "id", Op::Gt, vec![Value::Float(-0.5)])],
+        ];
+        for terms in &cases {
+            assert_same_rows(&path, terms);
+        }
+
+        // the reference above filters with the same terms, so check what they kept too
+        let rows = |terms: &[FilterTerm]| filtered_read(&path, terms).map_or(0, |(_, _, b)| b.num_rows());
+        assert_eq!(rows(&[term(&schema, "id", Op::Lt, vec![Value::Float(1.5)])]), 2);
+        assert_eq!(rows(&[term(&schema, "id", Op::Eq, vec![Value::Float(1.5)])]), 0);
+        assert_eq!(rows(&[term(&schema, "id", Op::Gt, vec![Value::Float(-0.5)])]), 1000);
+        assert_eq!(rows(&[term(&schema, "code", Op::Ge, vec![Value::Float(97.5)])]), 20);
+        assert_eq!(rows(&[term(&schema, "id", Op::Eq, vec![Value::Float(2.0)])]), 1);
+    }
+
+    #[test]
+    fn stats_prune_row_groups_and_pages() {
+        let path = write_file("pages");
+        let schema = builder(&path).schema().clone();
+
+        let (groups, selection, batch) = filtered_read(&path, &[term(&schema, "id", Op::Ge, vec![Value::Int(955)])]).unwrap();
+        assert_eq!(groups, vec![9]);
+        // pages 950..960 onwards, the row filter drops 950..955
+        assert_eq!(selection.unwrap().row_count(), 50);
+        assert_eq!(batch.num_rows(), 45);
+
+        let (groups, selection, _) = filtered_read(&path, &[term(&schema, "id", Op::In, vec![Value::Int(3), Value::Int(777)])]).unwrap();
+        assert_eq!(groups, vec![0, 7]);
+        assert_eq!(selection.unwrap().row_count(), 20);
+
+        assert!(filtered_read(&path, &[term(&schema, "id", Op::Gt, vec![Value::Int(999)])]).is_none());
+    }
+
+    #[test]
+    fn bloom_filters_prune_inside_min_max() {
+        let path = write_file("bloom");
+        let schema = builder(&path).schema().clone();
+
+        // both are between every row group's min and max, only the bloom filter knows they're absent
+        assert!(filtered_read(&path, &[term(&schema, "sym", Op::Eq, vec![Value::Str("MSFT".into())])]).is_none());
+        assert!(filtered_read(&path, &[term(&schema, "code", Op::In, vec![Value::Int(51), Value::Int(77)])]).is_none());
+
+        let terms = [term(&schema, "code", Op::In, vec![Value::Int(51), Value::Int(52)])];
+        let (groups, _, batch) = filtered_read(&path, &terms).unwrap();
+        assert_eq!(groups.len(), 10);
+        assert_eq!(batch.num_rows(), 20);
+        assert_same_rows(&path, &terms);
+    }
+
+    /// an int32 file in a scan unified to int64 has its literals bound as int64,
+    /// they still have to be hashed as i32 to find anything in the file's bloom filters
+    #[test]
+    fn bloom_int32_file_in_int64_scan() {
+        let path = write_file("widened");
+        let file_schema = builder(&path).schema().clone();
+        let fields: Vec<Field> = file_schema.fields().iter()
+            .map(|f| if f.name() == "code" { f.as_ref().clone().with_data_type(DataType::Int64) } else { f.as_ref().clone() })
+            .collect();
+        let scan_schema = Schema::new(fields);
+
+        let present = [term(&scan_schema, "code", Op::Eq, vec![Value::Int(52)])];
+        let (groups, _, batch) = filtered_read(&path, &present).unwrap();
+        assert_eq!(groups.len(), 10);
+        assert_eq!(batch.num_rows(), 20);
+        assert_same_rows(&path, &present);
+
+        assert!(filtered_read(&path, &[term(&scan_schema, "code", Op::Eq, vec![Value::Int(51)])]).is_none());
+
+        // row group 0's filter for code, which holds 0, 2, .. 98
+        let properties = ReaderProperties::builder().set_read_bloom_filter(true).build();
+        let options = ReadOptionsBuilder::new().with_reader_properties(properties).build();
+        let reader = SerializedFileReader::new_with_options(File::open(&path).unwrap(), options).unwrap();
+        let group = reader.get_row_group(0).unwrap();
+        let bloom = group.get_column_bloom_filter(3).unwrap();
+        assert!(bloom_check(bloom, &Value::Int(52), &DataType::Int64, PhysicalType::INT32));
+        assert!(bloom_check(bloom, &Value::Int(52), &DataType::Int32, PhysicalType::INT32));
+        // can't be in an int32 column at all
+        assert!(!bloom_check(bloom, &Value::Int(52 + (1 << 32)), &DataType::Int64, PhysicalType::INT32));
+        // hashed as i64 it wouldn't have been found
+        assert!(!bloom.check(&52i64));
+        // types we can't hash like the writer are always a maybe
+        assert!(bloom_check(bloom, &Value::Float(1.5), &DataType::Float64, PhysicalType::DOUBLE));
+    }
+
+    #[test]
+    fn missing_stats_never_prune() {
+        let schema = Schema::new(vec![Field::new("id", DataType::Int64, true)]);
+        let mins: ArrayRef = Arc::new(Int64Array::from(vec![Some(0), None, Some(100)]));
+        let maxes: ArrayRef = Arc::new(Int64Array::from(vec![Some(50), None, Some(150)]));
+
+        let eq = term(&schema, "id", Op::Eq, vec![Value::Int(120)]);
+        assert_eq!(eq.might_match(&mins, &maxes).unwrap(), vec![false, true, true]);
+        let lt = term(&schema, "id", Op::Lt, vec![Value::Int(0)]);
+        assert_eq!(lt.might_match(&mins, &maxes).unwrap(), vec![false, true, false]);
+        let ne = term(&schema, "id", Op::Ne, vec![Value::Int(0)]);
+        assert_eq!(ne.might_match(&mins, &maxes).unwrap(), vec![true, true, true]);
+    }
+}